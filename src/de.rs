@@ -0,0 +1,896 @@
+//! Contains a decoder which maps XML documents to Rust values.
+//!
+//! Values are decoded with `rustc-serialize`, so any type which implements `Decodable`,
+//! including types with `#[derive(RustcDecodable)]`, can be read from a document:
+//!
+//! * a struct is decoded from an element; every field is taken from the attribute
+//!   with the same local name if there is one, and from the child elements with the
+//!   same local name otherwise;
+//! * a sequence is decoded from all matching child elements, in document order; a
+//!   sequence of primitive values can also be read from a whitespace-separated attribute;
+//! * an `Option` is `None` when neither an attribute nor a child element is present;
+//! * an enum variant is chosen by the local name of the element, or of its only child
+//!   element; unit variants can also be chosen by text, e.g. by an attribute value
+//!   or by the text of an element;
+//! * primitive values and strings are parsed from attribute values or from the text
+//!   of elements.
+//!
+//! The default rules can be overridden by a `Mapping`, which can map fields to
+//! namespace-qualified attributes and elements or to the text content of an element.
+//!
+//! Errors carry the position of the element which could not be decoded.
+
+use std::fmt;
+use std::error;
+use std::str::FromStr;
+use std::io::{Buffer, BufReader};
+
+use rustc_serialize::{self, Decodable};
+
+use common::{Error, HasPosition, is_whitespace_char};
+use name::OwnedName;
+use attribute::OwnedAttribute;
+use namespace::{NS_XML_URI, NS_XML_PREFIX};
+use mapping::{Mapping, FieldMapping};
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+
+/// Decoding error.
+#[derive(Clone, PartialEq)]
+pub enum DecoderError {
+    /// The document is not well-formed.
+    Syntax(Error),
+
+    /// The document does not match the structure of the decoded type.
+    Mapping(Error)
+}
+
+impl DecoderError {
+    /// Returns the underlying error, which contains the position and the message.
+    pub fn error(&self) -> &Error {
+        match *self {
+            DecoderError::Syntax(ref e) | DecoderError::Mapping(ref e) => e
+        }
+    }
+}
+
+impl HasPosition for DecoderError {
+    #[inline]
+    fn row(&self) -> usize { self.error().row() }
+
+    #[inline]
+    fn col(&self) -> usize { self.error().col() }
+}
+
+impl fmt::Show for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::Syntax(ref e) => write!(f, "Syntax error: {:?}", e),
+            DecoderError::Mapping(ref e) => write!(f, "Mapping error: {:?}", e)
+        }
+    }
+}
+
+impl error::Error for DecoderError {
+    #[inline]
+    fn description(&self) -> &str { self.error().msg() }
+
+    fn detail(&self) -> Option<String> { Some(format!("{:?}", self)) }
+}
+
+/// Result of decoding.
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+/// Decodes a value from the root element of the document read from the given reader.
+#[inline]
+pub fn from_reader<B: Buffer, T: Decodable>(reader: &mut EventReader<B>) -> DecodeResult<T> {
+    from_reader_with_mapping(reader, &Mapping::new())
+}
+
+/// Decodes a value from the root element of the document read from the given reader,
+/// using the given field mapping.
+pub fn from_reader_with_mapping<B: Buffer, T: Decodable>(reader: &mut EventReader<B>,
+                                                        mapping: &Mapping) -> DecodeResult<T> {
+    let mut builder = ItemBuilder::new();
+    loop {
+        let event = reader.next();
+        match event {
+            XmlEvent::Error(e) => return Err(DecoderError::Syntax(e)),
+            XmlEvent::EndDocument => return Err(DecoderError::Mapping(
+                Error::new(reader, "Document does not contain an element".to_string())
+            )),
+            _ => {}
+        }
+        if let Some(root) = builder.push(event, reader) {
+            return Decoder::new(&root, mapping).decode();
+        }
+    }
+}
+
+/// Decodes a value from the root element of the given document.
+#[inline]
+pub fn from_str<T: Decodable>(source: &str) -> DecodeResult<T> {
+    from_reader(&mut EventReader::new(BufReader::new(source.as_bytes())))
+}
+
+/// Decodes a value from the root element of the given document, using the given
+/// field mapping.
+#[inline]
+pub fn from_str_with_mapping<T: Decodable>(source: &str, mapping: &Mapping) -> DecodeResult<T> {
+    from_reader_with_mapping(&mut EventReader::new(BufReader::new(source.as_bytes())), mapping)
+}
+
+/// An element read from the document, together with its position.
+pub struct Item {
+    name: OwnedName,
+    attributes: Vec<OwnedAttribute>,
+    children: Vec<Item>,
+    text: String,
+    row: usize,
+    col: usize
+}
+
+impl Item {
+    fn attribute(&self, namespace: Option<&str>, local_name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|a| a.name.has_expanded_name(namespace, local_name))
+            .map(|a| a.value.as_slice())
+    }
+}
+
+impl HasPosition for Item {
+    #[inline]
+    fn row(&self) -> usize { self.row }
+
+    #[inline]
+    fn col(&self) -> usize { self.col }
+}
+
+/// Builds items from a stream of events.
+pub struct ItemBuilder {
+    stack: Vec<Item>
+}
+
+impl ItemBuilder {
+    /// Creates a builder without open elements.
+    #[inline]
+    pub fn new() -> ItemBuilder {
+        ItemBuilder { stack: Vec::new() }
+    }
+
+    /// Adds an event read at the current position of `position` and returns an item
+    /// if the event closed an outermost element.
+    ///
+    /// Events outside of elements are ignored.
+    pub fn push<P: HasPosition>(&mut self, event: XmlEvent, position: &P) -> Option<Item> {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                self.stack.push(Item {
+                    name: name,
+                    attributes: attributes,
+                    children: Vec::new(),
+                    text: String::new(),
+                    row: position.row(),
+                    col: position.col()
+                });
+            }
+
+            XmlEvent::EndElement { .. } => {
+                let item = self.stack.pop().unwrap();
+                match self.stack.last_mut() {
+                    Some(parent) => parent.children.push(item),
+                    None => return Some(item)
+                }
+            }
+
+            XmlEvent::Characters(data) | XmlEvent::Whitespace(data) | XmlEvent::CData(data) =>
+                if let Some(item) = self.stack.last_mut() {
+                    item.text.push_str(data.as_slice());
+                },
+
+            _ => {}
+        }
+        None
+    }
+}
+
+/// An iterator which decodes elements with the given expanded name one at a time.
+///
+/// Only the element being decoded is kept in memory. Names in records are resolved
+/// using all namespace declarations in scope, and a record without `xml:lang` attribute
+/// gets the value inherited from its ancestors, so a field mapped to `xml:lang` sees it.
+///
+/// Elements nested inside a record are not returned separately. A syntax error ends
+/// the iteration; a record which cannot be decoded is returned as an error and the
+/// iteration continues.
+pub struct Records<'a, B: 'a, T> {
+    reader: &'a mut EventReader<B>,
+    namespace: Option<String>,
+    local_name: String,
+    mapping: Mapping,
+    finished: bool
+}
+
+impl<'a, B: Buffer, T: Decodable> Records<'a, B, T> {
+    /// Creates an iterator which decodes elements with the given namespace URI and
+    /// local name using the given field mapping; `None` namespace matches elements
+    /// without a namespace.
+    pub fn new(reader: &'a mut EventReader<B>, namespace: Option<&str>, local_name: &str,
+               mapping: Mapping) -> Records<'a, B, T> {
+        Records {
+            reader: reader,
+            namespace: namespace.map(|ns| ns.to_string()),
+            local_name: local_name.to_string(),
+            mapping: mapping,
+            finished: false
+        }
+    }
+
+    fn read_record(&mut self, start: XmlEvent) -> DecodeResult<T> {
+        let lang = self.reader.context().lang().map(|l| l.to_string());
+        let mut builder = ItemBuilder::new();
+        let mut event = start;
+        loop {
+            if let Some(mut item) = builder.push(event, &*self.reader) {
+                if let Some(lang) = lang {
+                    if item.attribute(Some(NS_XML_URI), "lang").is_none() {
+                        item.attributes.push(OwnedAttribute::new(
+                            OwnedName::qualified("lang", NS_XML_URI, Some(NS_XML_PREFIX)), lang
+                        ));
+                    }
+                }
+                return decode_item::<T>(&item, &self.mapping);
+            }
+            event = self.reader.next();
+            if let XmlEvent::Error(e) = event {
+                return Err(DecoderError::Syntax(e));
+            }
+        }
+    }
+}
+
+impl<'a, B: Buffer, T: Decodable> Iterator for Records<'a, B, T> {
+    type Item = DecodeResult<T>;
+
+    fn next(&mut self) -> Option<DecodeResult<T>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            match self.reader.next() {
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    let namespace = self.namespace.as_ref().map(|ns| ns.as_slice());
+                    if name.has_expanded_name(namespace, self.local_name.as_slice()) {
+                        let start = XmlEvent::StartElement { name: name, attributes: attributes, namespace: namespace };
+                        let result = self.read_record(start);
+                        if let Err(DecoderError::Syntax(_)) = result {
+                            self.finished = true;
+                        }
+                        return Some(result);
+                    }
+                }
+                XmlEvent::EndDocument => {
+                    self.finished = true;
+                    return None;
+                }
+                XmlEvent::Error(e) => {
+                    self.finished = true;
+                    return Some(Err(DecoderError::Syntax(e)));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn decode_item<T: Decodable>(item: &Item, mapping: &Mapping) -> DecodeResult<T> {
+    Decoder::new(item, mapping).decode()
+}
+
+/// A value which is being decoded.
+#[derive(Clone)]
+enum Value<'a> {
+    /// A single element.
+    Element(&'a Item),
+
+    /// Several elements with the same name; never empty.
+    Elements(Vec<&'a Item>),
+
+    /// An attribute value or text.
+    Text(String),
+
+    /// A missing attribute or element.
+    Absent
+}
+
+/// A decoder of values from an element tree.
+pub struct Decoder<'a> {
+    mapping: &'a Mapping,
+    stack: Vec<Value<'a>>,
+    // names of the structs being decoded and elements they are decoded from
+    structs: Vec<(String, &'a Item)>,
+    // field currently being decoded, for error messages
+    fields: Vec<String>
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a decoder which decodes a value from the given item.
+    pub fn new(root: &'a Item, mapping: &'a Mapping) -> Decoder<'a> {
+        Decoder {
+            mapping: mapping,
+            stack: vec![Value::Element(root)],
+            structs: Vec::new(),
+            fields: Vec::new()
+        }
+    }
+
+    /// Decodes a value.
+    #[inline]
+    pub fn decode<T: Decodable>(&mut self) -> DecodeResult<T> {
+        Decodable::decode(self)
+    }
+
+    fn top(&self) -> &Value<'a> {
+        self.stack.last().unwrap()
+    }
+
+    fn with<T, F>(&mut self, value: Value<'a>, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.stack.push(value);
+        let result = f(self);
+        self.stack.pop();
+        result
+    }
+
+    /// Creates an error positioned at the innermost element being decoded.
+    fn mapping_error(&self, msg: String) -> DecoderError {
+        let element = self.stack.iter().rev().filter_map(|v| match *v {
+            Value::Element(e) => Some(e),
+            Value::Elements(ref v) => Some(v[0]),
+            _ => None
+        }).next();
+        let msg = match self.fields.last() {
+            Some(field) => format!("field {}: {}", field, msg),
+            None => msg
+        };
+        match element {
+            Some(e) => DecoderError::Mapping(Error::new(e, msg)),
+            None => DecoderError::Mapping(Error::new_full(0, 0, msg))
+        }
+    }
+
+    fn fail<T>(&self, msg: String) -> DecodeResult<T> {
+        Err(self.mapping_error(msg))
+    }
+
+    fn text(&self) -> DecodeResult<String> {
+        match *self.top() {
+            Value::Text(ref s) => Ok(s.clone()),
+            Value::Element(e) => Ok(e.text.clone()),
+            Value::Elements(ref v) if v.len() == 1 => Ok(v[0].text.clone()),
+            Value::Elements(_) => self.fail("expected a single element, found several".to_string()),
+            Value::Absent => self.fail("missing value".to_string())
+        }
+    }
+
+    fn element(&self) -> DecodeResult<&'a Item> {
+        match *self.top() {
+            Value::Element(e) => Ok(e),
+            Value::Elements(ref v) if v.len() == 1 => Ok(v[0]),
+            Value::Elements(_) => self.fail("expected a single element, found several".to_string()),
+            Value::Text(_) => self.fail("expected an element, found text".to_string()),
+            Value::Absent => self.fail("missing element".to_string())
+        }
+    }
+
+    fn parse<T: FromStr>(&self, what: &str) -> DecodeResult<T> {
+        let text = try!(self.text());
+        match text.as_slice().trim().parse() {
+            Some(value) => Ok(value),
+            None => self.fail(format!("cannot parse {:?} as {}", text, what))
+        }
+    }
+
+    /// Finds the value of a field of the struct decoded from the given element.
+    fn field(&self, element: &'a Item, struct_name: &str, field: &str) -> Value<'a> {
+        let mapping: &'a Mapping = self.mapping;
+        match mapping.get(Some(struct_name), field) {
+            Some(&FieldMapping::Text) => Value::Text(element.text.clone()),
+            Some(&FieldMapping::Attribute(ref name)) =>
+                match element.attribute(name.namespace_as_ref(), name.local_name.as_slice()) {
+                    Some(value) => Value::Text(value.to_string()),
+                    None => Value::Absent
+                },
+            Some(&FieldMapping::Element(ref name)) => elements(element.children.iter()
+                .filter(|e| e.name.has_expanded_name(name.namespace_as_ref(), name.local_name.as_slice()))
+                .collect()),
+            None => match element.attribute(None, field) {
+                Some(value) => Value::Text(value.to_string()),
+                None => elements(element.children.iter().filter(|e| e.name.local_name == field).collect())
+            }
+        }
+    }
+
+    /// Chooses an enum variant by the name of the current element or by text.
+    fn variant(&self, names: &[&str]) -> DecodeResult<(usize, Value<'a>)> {
+        if let Value::Text(ref s) = *self.top() {
+            return match names.iter().position(|n| *n == s.as_slice().trim()) {
+                Some(i) => Ok((i, Value::Text(s.clone()))),
+                None => self.fail(format!("unknown variant {:?}", s))
+            };
+        }
+
+        let element = try!(self.element());
+        if let Some(i) = names.iter().position(|n| *n == element.name.local_name) {
+            return Ok((i, Value::Element(element)));
+        }
+        // the element can wrap an element which chooses the variant, or name a unit variant
+        // in its text
+        match element.children.len() {
+            0 => if let Some(i) = names.iter().position(|n| *n == element.text.as_slice().trim()) {
+                return Ok((i, Value::Text(element.text.clone())));
+            },
+            1 => {
+                let child = &element.children[0];
+                if let Some(i) = names.iter().position(|n| *n == child.name.local_name) {
+                    return Ok((i, Value::Element(child)));
+                }
+            }
+            _ => {}
+        }
+        self.fail(format!("no variant matches element {}", element.name))
+    }
+
+    /// Decodes an argument of a tuple, a tuple struct or an enum variant.
+    ///
+    /// Several elements are decoded positionally; otherwise the only argument is
+    /// decoded from the current value itself.
+    fn argument<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let value = match *self.top() {
+            Value::Elements(ref v) if v.len() > 1 => match v.get(idx) {
+                Some(&e) => Value::Element(e),
+                None => Value::Absent
+            },
+            ref value if idx == 0 => value.clone(),
+            _ => Value::Absent
+        };
+        self.with(value, f)
+    }
+
+    fn struct_field<T, F>(&mut self, f_name: &str, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let value = match self.structs.last() {
+            Some(&(ref struct_name, element)) => self.field(element, struct_name.as_slice(), f_name),
+            None => return self.fail("a field outside of a struct".to_string())
+        };
+        self.fields.push(f_name.to_string());
+        let result = self.with(value, f);
+        self.fields.pop();
+        result
+    }
+}
+
+fn elements<'a>(items: Vec<&'a Item>) -> Value<'a> {
+    if items.is_empty() { Value::Absent } else { Value::Elements(items) }
+}
+
+fn words(s: &str) -> Vec<&str> {
+    s.split(|&: c: char| is_whitespace_char(c)).filter(|w| !w.is_empty()).collect()
+}
+
+impl<'a> rustc_serialize::Decoder for Decoder<'a> {
+    type Error = DecoderError;
+
+    fn read_nil(&mut self) -> DecodeResult<()> { Ok(()) }
+
+    fn read_usize(&mut self) -> DecodeResult<usize> { self.parse("usize") }
+    fn read_u64(&mut self) -> DecodeResult<u64> { self.parse("u64") }
+    fn read_u32(&mut self) -> DecodeResult<u32> { self.parse("u32") }
+    fn read_u16(&mut self) -> DecodeResult<u16> { self.parse("u16") }
+    fn read_u8(&mut self) -> DecodeResult<u8> { self.parse("u8") }
+    fn read_isize(&mut self) -> DecodeResult<isize> { self.parse("isize") }
+    fn read_i64(&mut self) -> DecodeResult<i64> { self.parse("i64") }
+    fn read_i32(&mut self) -> DecodeResult<i32> { self.parse("i32") }
+    fn read_i16(&mut self) -> DecodeResult<i16> { self.parse("i16") }
+    fn read_i8(&mut self) -> DecodeResult<i8> { self.parse("i8") }
+    fn read_f64(&mut self) -> DecodeResult<f64> { self.parse("f64") }
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.parse("f32") }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        let text = try!(self.text());
+        match text.as_slice().trim() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => self.fail(format!("cannot parse {:?} as bool", text))
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let text = try!(self.text());
+        let mut chars = text.as_slice().chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => self.fail(format!("expected a single character, found {:?}", text))
+        }
+    }
+
+    fn read_str(&mut self) -> DecodeResult<String> { self.text() }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder<'a>, usize) -> DecodeResult<T>
+    {
+        let (idx, value) = try!(self.variant(names));
+        self.stack.push(value);
+        let result = f(self, idx);
+        self.stack.pop();
+        result
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, a_idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.argument(a_idx, f)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder<'a>, usize) -> DecodeResult<T>
+    {
+        let (idx, value) = try!(self.variant(names));
+        let element = match value {
+            Value::Element(e) => e,
+            _ => return self.fail("expected an element for a struct variant".to_string())
+        };
+        self.structs.push((names[idx].to_string(), element));
+        self.stack.push(value);
+        let mut f = f;
+        let result = f(self, idx);
+        self.stack.pop();
+        self.structs.pop();
+        result
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, f_name: &str, _f_idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.struct_field(f_name, f)
+    }
+
+    fn read_struct<T, F>(&mut self, s_name: &str, _len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let element = try!(self.element());
+        self.structs.push((s_name.to_string(), element));
+        let result = f(self);
+        self.structs.pop();
+        result
+    }
+
+    fn read_struct_field<T, F>(&mut self, f_name: &str, _f_idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.struct_field(f_name, f)
+    }
+
+    fn read_tuple<T, F>(&mut self, _len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, a_idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.argument(a_idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self, _s_name: &str, _len: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, a_idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        self.argument(a_idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder<'a>, bool) -> DecodeResult<T>
+    {
+        let present = match *self.top() {
+            Value::Absent => false,
+            _ => true
+        };
+        f(self, present)
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>, usize) -> DecodeResult<T>
+    {
+        let len = match *self.top() {
+            Value::Element(_) => 1,
+            Value::Elements(ref v) => v.len(),
+            Value::Text(ref s) => words(s.as_slice()).len(),
+            Value::Absent => 0
+        };
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let value = match *self.top() {
+            Value::Element(e) => Value::Element(e),
+            Value::Elements(ref v) => Value::Element(v[idx]),
+            Value::Text(ref s) => Value::Text(words(s.as_slice())[idx].to_string()),
+            Value::Absent => Value::Absent
+        };
+        self.with(value, f)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>, usize) -> DecodeResult<T>
+    {
+        // a map is decoded from child elements, keyed by their local names
+        let len = try!(self.element()).children.len();
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let element = try!(self.element());
+        self.with(Value::Text(element.children[idx].name.local_name.clone()), f)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T>
+        where F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>
+    {
+        let element = try!(self.element());
+        self.with(Value::Element(&element.children[idx]), f)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        self.mapping_error(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{File, BufferedReader};
+
+    use common::HasPosition;
+    use name::OwnedName;
+    use mapping::Mapping;
+    use namespace::NS_XML_URI;
+    use reader::EventReader;
+    use super::{DecoderError, from_reader_with_mapping, from_str};
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Project {
+        name: String,
+        libraries: Libraries,
+        module: Vec<Module>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Libraries {
+        library: Vec<Library>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Library {
+        group_id: String,
+        artifact_id: String,
+        version: String
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Module {
+        name: String,
+        files: Files,
+        libraries: Option<Libraries>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Files {
+        file: Vec<SourceFile>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct SourceFile {
+        name: String,
+        kind: String,
+        content: String
+    }
+
+    #[test]
+    fn decodes_attributes_children_and_text() {
+        let mapping = Mapping::new()
+            .attribute("group_id", OwnedName::local("groupId"))
+            .attribute("artifact_id", OwnedName::local("artifactId"))
+            .attribute("SourceFile.kind", OwnedName::local("type"))
+            .text("SourceFile.content");
+        let file = File::open(&Path::new("data/sample_1.xml"));
+        let mut reader = EventReader::new(BufferedReader::new(file));
+        let project: Project = from_reader_with_mapping(&mut reader, &mapping).unwrap();
+
+        assert_eq!(project.name.as_slice(), "project-name");
+        assert_eq!(project.libraries.library.len(), 2);
+        assert_eq!(project.libraries.library[0].artifact_id.as_slice(), "<name>");
+        assert_eq!(project.libraries.library[1].version.as_slice(), "999");
+
+        assert_eq!(project.module.len(), 2);
+        let first = &project.module[0];
+        assert_eq!(first.files.file.len(), 3);
+        assert_eq!(first.files.file[2].kind.as_slice(), "xml");
+        assert_eq!(first.files.file[0].content.as_slice().trim(), "Some <java> class");
+        assert_eq!(first.libraries.as_ref().unwrap().library[0].group_id.as_slice(), "junit");
+        assert!(project.module[1].libraries.is_none());
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Data {
+        datum: Vec<Datum>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Datum {
+        id: u32,
+        name: String,
+        other_name: String,
+        values: Vec<f64>,
+        header: Vec<Header>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Header {
+        name: String,
+        value: String
+    }
+
+    #[test]
+    fn decodes_namespace_qualified_fields() {
+        let mapping = Mapping::new()
+            .element("Datum.name", OwnedName::qualified("name", "urn:example:namespace", Some("p")))
+            .element("Datum.other_name", OwnedName::qualified("name", "urn:example:double", Some("d")))
+            .element("Datum.values", OwnedName::qualified("arg", "urn:example:double", Some("d")))
+            .text("Header.value");
+        let file = File::open(&Path::new("data/sample_2.xml"));
+        let mut reader = EventReader::new(BufferedReader::new(file));
+        let data: Data = from_reader_with_mapping(&mut reader, &mapping).unwrap();
+
+        assert_eq!(data.datum.len(), 1);
+        let datum = &data.datum[0];
+        assert_eq!(datum.id, 34);
+        assert_eq!(datum.name.as_slice(), "Name");
+        assert_eq!(datum.other_name.as_slice(), "Another name");
+        assert_eq!(datum.values, vec![0.3, 0.2]);
+        assert_eq!(datum.header.len(), 2);
+        assert_eq!(datum.header[0].name.as_slice(), "Header-1");
+        assert_eq!(datum.header[1].value.as_slice().trim(), "Some bigger value");
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Drawing {
+        state: State,
+        sizes: Vec<u32>,
+        shape: Vec<Shape>
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    enum State {
+        Active,
+        Inactive
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    enum Shape {
+        Circle(Circle),
+        Square(Square)
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Circle {
+        r: f64
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Square {
+        side: f64
+    }
+
+    #[test]
+    fn decodes_enums_and_lists() {
+        let drawing: Drawing = from_str(r#"<drawing state="Inactive" sizes="1 2  3">
+            <shape><Circle r="1.5"/></shape>
+            <shape><Square><side>2</side></Square></shape>
+        </drawing>"#).unwrap();
+
+        assert_eq!(drawing, Drawing {
+            state: State::Inactive,
+            sizes: vec![1, 2, 3],
+            shape: vec![
+                Shape::Circle(Circle { r: 1.5 }),
+                Shape::Square(Square { side: 2.0 })
+            ]
+        });
+    }
+
+    #[test]
+    fn errors_carry_positions() {
+        let result: Result<Drawing, DecoderError> = from_str("<drawing state=\"Active\"\n  sizes=\"1\">\n  <shape><Triangle/></shape>\n</drawing>");
+        match result {
+            Err(DecoderError::Mapping(ref e)) => {
+                assert_eq!(e.row(), 2);
+                assert!(e.msg().contains("no variant"));
+            }
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+
+        let result: Result<Circle, DecoderError> = from_str("<circle r=\"1\">");
+        match result {
+            Err(DecoderError::Syntax(_)) => {}
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+
+        let result: Result<Circle, DecoderError> = from_str("<circle/>");
+        match result {
+            Err(DecoderError::Mapping(ref e)) => assert!(e.msg().contains("field r")),
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[derive(RustcDecodable, Show, PartialEq)]
+    struct Entry {
+        id: u32,
+        title: String,
+        lang: Option<String>
+    }
+
+    #[test]
+    fn decodes_records_one_at_a_time() {
+        let mut reader = EventReader::new_from_str_slice(r#"<feed xmlns="urn:feed" xmlns:a="urn:atom" xml:lang="en">
+            <meta><entry id="0"><a:title>Zero</a:title></entry></meta>
+            <o:entry xmlns:o="urn:other" id="9"><a:title>Other</a:title></o:entry>
+            <entry id="1"><a:title>First</a:title></entry>
+            <group xml:lang="de">
+                <entry id="2"><a:title>Zweite</a:title></entry>
+                <entry id="x"><a:title>Broken</a:title></entry>
+                <entry id="4" xml:lang="fr"><a:title>Quatre</a:title></entry>
+            </group>
+        </feed>"#);
+        let mapping = Mapping::new()
+            .element("Entry.title", OwnedName::qualified("title", "urn:atom", Some("a")))
+            .attribute("Entry.lang", OwnedName::qualified("lang", NS_XML_URI, Some("xml")));
+
+        let records: Vec<Result<Entry, DecoderError>> =
+            reader.records_with_mapping(Some("urn:feed"), "entry", mapping).collect();
+        assert_eq!(records.len(), 5);
+
+        fn get(records: &[Result<Entry, DecoderError>], i: usize) -> &Entry {
+            records[i].as_ref().ok().expect("Cannot decode record")
+        }
+        assert_eq!(get(&*records, 0).id, 0);
+        assert_eq!(get(&*records, 1).title.as_slice(), "First");
+        assert_eq!(get(&*records, 1).lang.as_ref().map(|l| l.as_slice()), Some("en"));
+        assert_eq!(get(&*records, 2).lang.as_ref().map(|l| l.as_slice()), Some("de"));
+        assert_eq!(get(&*records, 4).lang.as_ref().map(|l| l.as_slice()), Some("fr"));
+        match records[3] {
+            Err(DecoderError::Mapping(ref e)) => assert_eq!(e.row(), 6),
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Contains a compact immutable representation of XML documents.
+//!
+//! `CompactDocument` is intended for large documents which are only queried. All nodes
+//! are stored in a flat array in document order, names are interned, and all character
+//! data, including attribute values, is stored in a single string buffer which nodes
+//! refer to by ranges. Because nodes are stored in document order, descendants of every
+//! node occupy a contiguous range of the array right after the node itself.
+//!
+//! Namespace declarations are not stored; names carry resolved namespace URIs.
+
+use std::u32;
+use std::io::Buffer;
+use std::collections::HashMap;
+
+use common::Error;
+use name::OwnedName;
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+
+const NO_NODE: u32 = u32::MAX;
+
+/// Kind of a node in a `CompactDocument`.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum NodeKind {
+    /// The document node, which is the parent of the root element.
+    Document,
+
+    /// An element.
+    Element,
+
+    /// Character data.
+    Text,
+
+    /// A CDATA section.
+    CData,
+
+    /// A comment.
+    Comment,
+
+    /// A processing instruction; its target is available as the node name.
+    ProcessingInstruction
+}
+
+/// Identifier of a node in a `CompactDocument`.
+///
+/// Identifiers are assigned in document order, so they can be compared to find
+/// out which node comes first.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct NodeId(u32);
+
+struct NodeData {
+    kind: NodeKind,
+    name: u32,        // index in names, or NO_NODE
+    parent: u32,      // index of the parent node, or NO_NODE for the document node
+    end: u32,         // index of the first node after the subtree of this node
+    text: (u32, u32), // content range in the text buffer
+    attributes: (u32, u32)  // range in the attributes array
+}
+
+struct AttributeData {
+    name: u32,
+    value: (u32, u32)
+}
+
+/// A compact immutable XML document.
+pub struct CompactDocument {
+    nodes: Vec<NodeData>,
+    names: Vec<OwnedName>,
+    attributes: Vec<AttributeData>,
+    text: String
+}
+
+/// Interns names during document construction.
+struct NameTable {
+    names: Vec<OwnedName>,
+    indices: HashMap<OwnedName, u32>
+}
+
+impl NameTable {
+    fn intern(&mut self, name: OwnedName) -> Option<u32> {
+        if let Some(&i) = self.indices.get(&name) {
+            return Some(i);
+        }
+        let i = checked_index(self.names.len());
+        if let Some(i) = i {
+            self.names.push(name.clone());
+            self.indices.insert(name, i);
+        }
+        i
+    }
+}
+
+/// Converts a length or an offset into a `u32` index; `None` if it does not fit,
+/// `NO_NODE` being reserved.
+#[inline]
+fn checked_index(len: usize) -> Option<u32> {
+    if len < NO_NODE as usize { Some(len as u32) } else { None }
+}
+
+impl CompactDocument {
+    /// Reads a whole document from the given reader in a single pass.
+    pub fn from_reader<B: Buffer>(reader: &mut EventReader<B>) -> Result<CompactDocument, Error> {
+        let mut doc = CompactDocument {
+            nodes: Vec::new(),
+            names: Vec::new(),
+            attributes: Vec::new(),
+            text: String::new()
+        };
+        let mut names = NameTable { names: Vec::new(), indices: HashMap::new() };
+
+        // offsets are stored as u32, so documents with more data cannot be represented
+        macro_rules! checked(
+            ($e:expr) => (match $e {
+                Some(value) => value,
+                None => return Err(Error::new(
+                    &*reader, "Document is too large for a compact representation".to_string()
+                ))
+            })
+        );
+        // indices of open elements, starting with the document node
+        let mut stack: Vec<u32> = vec![0];
+
+        doc.nodes.push(NodeData {
+            kind: NodeKind::Document,
+            name: NO_NODE,
+            parent: NO_NODE,
+            end: NO_NODE,
+            text: (0, 0),
+            attributes: (0, 0)
+        });
+
+        loop {
+            let parent = *stack.last().unwrap();
+            let (kind, name, content) = match reader.next() {
+                XmlEvent::StartDocument { .. } => continue,
+
+                XmlEvent::EndDocument => {
+                    doc.nodes[0].end = checked!(checked_index(doc.nodes.len()));
+                    doc.names = names.names;
+                    return Ok(doc);
+                }
+
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    let start = checked!(checked_index(doc.attributes.len()));
+                    for attr in attributes.into_iter() {
+                        let value = checked!(doc.push_text(attr.value.as_slice()));
+                        let name = checked!(names.intern(attr.name));
+                        doc.attributes.push(AttributeData { name: name, value: value });
+                    }
+                    let end = checked!(checked_index(doc.attributes.len()));
+
+                    let name = checked!(names.intern(name));
+                    stack.push(checked!(checked_index(doc.nodes.len())));
+                    doc.nodes.push(NodeData {
+                        kind: NodeKind::Element,
+                        name: name,
+                        parent: parent,
+                        end: NO_NODE,  // set at the end of the element
+                        text: (0, 0),
+                        attributes: (start, end)
+                    });
+                    continue;
+                }
+
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().unwrap();
+                    doc.nodes[element as usize].end = checked!(checked_index(doc.nodes.len()));
+                    continue;
+                }
+
+                XmlEvent::Characters(data) | XmlEvent::Whitespace(data) => {
+                    // adjacent text is merged into the previous text node
+                    let last = doc.nodes.len() - 1;
+                    if doc.nodes[last].kind == NodeKind::Text && doc.nodes[last].parent == parent {
+                        doc.text.push_str(data.as_slice());
+                        doc.nodes[last].text.1 = checked!(checked_index(doc.text.len()));
+                        continue;
+                    }
+                    (NodeKind::Text, NO_NODE, data)
+                }
+
+                XmlEvent::CData(data) => (NodeKind::CData, NO_NODE, data),
+
+                XmlEvent::Comment(data) => (NodeKind::Comment, NO_NODE, data),
+
+                XmlEvent::ProcessingInstruction { name, data } =>
+                    (NodeKind::ProcessingInstruction, checked!(names.intern(OwnedName::local(name))),
+                     data.unwrap_or(String::new())),
+
+                XmlEvent::Error(e) => return Err(e)
+            };
+
+            let text = checked!(doc.push_text(content.as_slice()));
+            // the end of the subtree, index + 1, must fit as well
+            let index = checked!(checked_index(doc.nodes.len() + 1)) - 1;
+            doc.nodes.push(NodeData {
+                kind: kind,
+                name: name,
+                parent: parent,
+                end: index + 1,
+                text: text,
+                attributes: (0, 0)
+            });
+        }
+    }
+
+    fn push_text(&mut self, s: &str) -> Option<(u32, u32)> {
+        match (checked_index(self.text.len()), checked_index(self.text.len() + s.len())) {
+            (Some(start), Some(end)) => {
+                self.text.push_str(s);
+                Some((start, end))
+            }
+            _ => None
+        }
+    }
+
+    #[inline]
+    fn slice(&self, range: (u32, u32)) -> &str {
+        self.text.as_slice().slice(range.0 as usize, range.1 as usize)
+    }
+
+    /// Returns the number of nodes in the document, including the document node.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the document node.
+    #[inline]
+    pub fn document_node(&self) -> CompactNode {
+        CompactNode { doc: self, index: 0 }
+    }
+
+    /// Returns the root element.
+    ///
+    /// Panics if the document does not contain an element.
+    pub fn root(&self) -> CompactNode {
+        self.document_node().children()
+            .find(|n| n.kind() == NodeKind::Element)
+            .expect("Document does not contain a root element")
+    }
+
+    /// Returns the node with the given identifier.
+    ///
+    /// Panics if the identifier does not belong to this document.
+    #[inline]
+    pub fn node(&self, id: NodeId) -> CompactNode {
+        assert!((id.0 as usize) < self.nodes.len(), "Invalid node identifier");
+        CompactNode { doc: self, index: id.0 }
+    }
+
+    /// Returns all distinct names used in the document.
+    #[inline]
+    pub fn names(&self) -> &[OwnedName] {
+        self.names.as_slice()
+    }
+}
+
+/// A reference to a node of a `CompactDocument`.
+#[derive(Copy, Clone)]
+pub struct CompactNode<'a> {
+    doc: &'a CompactDocument,
+    index: u32
+}
+
+impl<'a> PartialEq for CompactNode<'a> {
+    fn eq(&self, other: &CompactNode<'a>) -> bool {
+        self.doc as *const CompactDocument == other.doc as *const CompactDocument &&
+        self.index == other.index
+    }
+}
+
+impl<'a> CompactNode<'a> {
+    #[inline]
+    fn data(&self) -> &'a NodeData {
+        &self.doc.nodes[self.index as usize]
+    }
+
+    #[inline]
+    fn at(&self, index: u32) -> CompactNode<'a> {
+        CompactNode { doc: self.doc, index: index }
+    }
+
+    /// Returns the identifier of this node.
+    #[inline]
+    pub fn id(&self) -> NodeId {
+        NodeId(self.index)
+    }
+
+    /// Returns the kind of this node.
+    #[inline]
+    pub fn kind(&self) -> NodeKind {
+        self.data().kind
+    }
+
+    /// Returns the name of an element or the target of a processing instruction.
+    pub fn name(&self) -> Option<&'a OwnedName> {
+        match self.data().name {
+            NO_NODE => None,
+            i => Some(&self.doc.names[i as usize])
+        }
+    }
+
+    /// Returns the content of a text, CDATA or comment node, or the data of
+    /// a processing instruction.
+    pub fn text(&self) -> Option<&'a str> {
+        match self.kind() {
+            NodeKind::Document | NodeKind::Element => None,
+            _ => Some(self.doc.slice(self.data().text))
+        }
+    }
+
+    /// Returns an iterator over attributes of an element as pairs of names and values.
+    #[inline]
+    pub fn attributes(&self) -> Attributes<'a> {
+        let (start, end) = self.data().attributes;
+        Attributes { doc: self.doc, next: start, end: end }
+    }
+
+    /// Returns the value of an attribute with the given namespace URI and local name.
+    pub fn attribute(&self, namespace: Option<&str>, local_name: &str) -> Option<&'a str> {
+        self.attributes()
+            .find(|&(name, _)| name.has_expanded_name(namespace, local_name))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the parent of this node.
+    pub fn parent(&self) -> Option<CompactNode<'a>> {
+        match self.data().parent {
+            NO_NODE => None,
+            i => Some(self.at(i))
+        }
+    }
+
+    /// Returns the first child of this node.
+    pub fn first_child(&self) -> Option<CompactNode<'a>> {
+        if self.index + 1 < self.data().end { Some(self.at(self.index + 1)) } else { None }
+    }
+
+    /// Returns the sibling which immediately follows this node.
+    pub fn next_sibling(&self) -> Option<CompactNode<'a>> {
+        match self.parent() {
+            Some(parent) if self.data().end < parent.data().end => Some(self.at(self.data().end)),
+            _ => None
+        }
+    }
+
+    /// Returns an iterator over children of this node.
+    #[inline]
+    pub fn children(&self) -> Children<'a> {
+        Children { next: self.first_child() }
+    }
+
+    /// Returns an iterator over child elements with the given namespace URI and local name.
+    pub fn children_named(&self, namespace: Option<&'a str>, local_name: &'a str) -> ChildrenNamed<'a> {
+        ChildrenNamed { children: self.children(), namespace: namespace, local_name: local_name }
+    }
+
+    /// Returns an iterator over descendants of this node in document order.
+    #[inline]
+    pub fn descendants(&self) -> Descendants<'a> {
+        Descendants { doc: self.doc, next: self.index + 1, end: self.data().end }
+    }
+
+    /// Returns the concatenation of all text and CDATA descendants of this node, or
+    /// the text of this node if it is not an element.
+    pub fn text_content(&self) -> String {
+        match self.kind() {
+            NodeKind::Document | NodeKind::Element => {
+                let mut result = String::new();
+                for node in self.descendants() {
+                    match node.kind() {
+                        NodeKind::Text | NodeKind::CData => result.push_str(node.text().unwrap()),
+                        _ => {}
+                    }
+                }
+                result
+            }
+            _ => self.text().unwrap().to_string()
+        }
+    }
+}
+
+/// An iterator over attributes of an element.
+pub struct Attributes<'a> {
+    doc: &'a CompactDocument,
+    next: u32,
+    end: u32
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (&'a OwnedName, &'a str);
+
+    fn next(&mut self) -> Option<(&'a OwnedName, &'a str)> {
+        if self.next < self.end {
+            let attr = &self.doc.attributes[self.next as usize];
+            self.next += 1;
+            Some((&self.doc.names[attr.name as usize], self.doc.slice(attr.value)))
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator over children of a node.
+pub struct Children<'a> {
+    next: Option<CompactNode<'a>>
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = CompactNode<'a>;
+
+    fn next(&mut self) -> Option<CompactNode<'a>> {
+        let current = self.next;
+        self.next = current.and_then(|n| n.next_sibling());
+        current
+    }
+}
+
+/// An iterator over child elements with a particular expanded name.
+pub struct ChildrenNamed<'a> {
+    children: Children<'a>,
+    namespace: Option<&'a str>,
+    local_name: &'a str
+}
+
+impl<'a> Iterator for ChildrenNamed<'a> {
+    type Item = CompactNode<'a>;
+
+    fn next(&mut self) -> Option<CompactNode<'a>> {
+        for child in self.children.by_ref() {
+            if child.kind() == NodeKind::Element &&
+               child.name().unwrap().has_expanded_name(self.namespace, self.local_name) {
+                return Some(child);
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over descendants of a node in document order.
+pub struct Descendants<'a> {
+    doc: &'a CompactDocument,
+    next: u32,
+    end: u32
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = CompactNode<'a>;
+
+    fn next(&mut self) -> Option<CompactNode<'a>> {
+        if self.next < self.end {
+            let node = CompactNode { doc: self.doc, index: self.next };
+            self.next += 1;
+            Some(node)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{File, BufferedReader};
+
+    use reader::EventReader;
+    use super::{CompactDocument, NodeKind, NO_NODE, checked_index};
+
+    #[test]
+    fn builds_compact_document() {
+        let file = File::open(&Path::new("data/sample_1.xml"));
+        let mut reader = EventReader::new(BufferedReader::new(file));
+        let doc = CompactDocument::from_reader(&mut reader).ok().expect("Cannot parse document");
+
+        let root = doc.root();
+        assert_eq!(root.name().unwrap().local_name.as_slice(), "project");
+        assert_eq!(root.attribute(None, "name"), Some("project-name"));
+        assert!(root.parent().unwrap() == doc.document_node());
+
+        let libraries = root.children_named(None, "libraries").next().unwrap();
+        let ids: Vec<&str> = libraries.children_named(None, "library")
+            .map(|l| l.attribute(None, "artifactId").unwrap())
+            .collect();
+        assert_eq!(ids, vec!["<name>", "\"cool-lib&"]);
+
+        let modules: Vec<_> = root.children_named(None, "module").collect();
+        assert_eq!(modules.len(), 2);
+        assert!(modules[0].id() < modules[1].id());
+        assert_eq!(modules[1].attribute(None, "name"), Some("module-2"));
+
+        let files = modules[0].descendants()
+            .filter(|n| n.kind() == NodeKind::Element &&
+                        n.name().unwrap().local_name.as_slice() == "file")
+            .count();
+        assert_eq!(files, 3);
+
+        let css = modules[1].descendants()
+            .find(|n| n.attribute(None, "type") == Some("css"))
+            .unwrap();
+        assert_eq!(css.text_content().as_slice().trim(), "Cascading style sheet: \u{A9} - \u{489}");
+
+        // "project", "libraries", "library", "groupId", "artifactId", "version",
+        // "module", "name", "files", "file", "type"
+        assert_eq!(doc.names().len(), 11);
+    }
+
+    #[test]
+    fn rejects_offsets_which_do_not_fit() {
+        assert_eq!(checked_index(0), Some(0));
+        assert_eq!(checked_index(NO_NODE as usize - 1), Some(NO_NODE - 1));
+        assert_eq!(checked_index(NO_NODE as usize), None);
+    }
+}
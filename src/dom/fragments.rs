@@ -0,0 +1,240 @@
+//! Contains an adapter which materializes selected subtrees of a stream as elements.
+//!
+//! `Fragments` streams through a document with a `PathMatcher` and builds an `Element`
+//! for every matching subtree, discarding the rest of the document. This is convenient for
+//! record-oriented documents, where the whole document does not fit into memory but every
+//! record does.
+//!
+//! Every fragment carries all namespace mappings which are in scope at its root element
+//! in its `namespace` field, so it can be serialized as a standalone document.
+
+use std::io::Buffer;
+
+use common::Error;
+use namespace::{Namespace, NamespaceIterable, NS_XML_PREFIX, NS_XMLNS_PREFIX};
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+use reader::context::ReaderContext;
+use reader::matcher::PathMatcher;
+
+use dom::{Element, Node, Text, CData, Comment, ProcessingInstruction, push_node};
+
+/// Prefix used to match elements by namespace URI in `Fragments::with_name()`.
+const NAME_PREFIX: &'static str = "fragment";
+
+/// An iterator over subtrees of a document which match path patterns.
+pub struct Fragments<B> {
+    matcher: PathMatcher<B>,
+    finished: bool
+}
+
+impl<B: Buffer> Fragments<B> {
+    /// Creates an adapter which returns subtrees matched by the given matcher.
+    #[inline]
+    pub fn new(matcher: PathMatcher<B>) -> Fragments<B> {
+        Fragments { matcher: matcher, finished: false }
+    }
+
+    /// Creates an adapter which returns subtrees matching the given path pattern.
+    ///
+    /// See `reader::matcher` module for the pattern syntax.
+    pub fn with_pattern(reader: EventReader<B>, pattern: &str) -> Result<Fragments<B>, String> {
+        let mut matcher = PathMatcher::new(reader);
+        try!(matcher.add_pattern(pattern));
+        Ok(Fragments::new(matcher))
+    }
+
+    /// Creates an adapter which returns subtrees rooted at elements with the given name,
+    /// at any depth.
+    ///
+    /// If `namespace` is `None`, elements without a namespace are matched; elements in
+    /// a default namespace are not.
+    pub fn with_name(reader: EventReader<B>, namespace: Option<&str>,
+                     local_name: &str) -> Result<Fragments<B>, String> {
+        let mut matcher = PathMatcher::new(reader);
+        match namespace {
+            Some(uri) => {
+                matcher.namespace(NAME_PREFIX, uri);
+                try!(matcher.add_pattern(format!("//{}:{}", NAME_PREFIX, local_name).as_slice()));
+            }
+            None => {
+                try!(matcher.add_pattern(format!("//{}", local_name).as_slice()));
+            }
+        }
+        Ok(Fragments::new(matcher))
+    }
+
+    /// Returns the underlying matcher, consuming the adapter.
+    #[inline]
+    pub fn into_inner(self) -> PathMatcher<B> {
+        self.matcher
+    }
+
+    /// Reads the next matching subtree and returns it together with the index of
+    /// the pattern that matched.
+    ///
+    /// `None` is returned at the end of the document.
+    pub fn next_fragment(&mut self) -> Result<Option<(usize, Element)>, Error> {
+        // elements which are currently open
+        let mut stack: Vec<Element> = Vec::new();
+
+        loop {
+            let (pattern, event) = match try!(self.matcher.next()) {
+                Some(m) => m,
+                None => return Ok(None)
+            };
+
+            let node = match event {
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    let namespace = if stack.is_empty() {
+                        in_scope_namespace(&self.matcher.context())
+                    } else {
+                        namespace
+                    };
+                    stack.push(Element {
+                        name: name,
+                        attributes: attributes,
+                        namespace: namespace,
+                        children: Vec::new()
+                    });
+                    continue;
+                }
+
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().unwrap();
+                    if stack.is_empty() {
+                        return Ok(Some((pattern, element)));
+                    }
+                    Node::Element(element)
+                }
+
+                XmlEvent::ProcessingInstruction { name, data } =>
+                    Node::ProcessingInstruction(ProcessingInstruction { name: name, data: data }),
+
+                XmlEvent::Characters(data) | XmlEvent::Whitespace(data) => Node::Text(Text(data)),
+
+                XmlEvent::CData(data) => Node::CData(CData(data)),
+
+                XmlEvent::Comment(data) => Node::Comment(Comment(data)),
+
+                // the matcher only returns events inside of elements
+                _ => continue
+            };
+
+            push_node(&mut stack.last_mut().unwrap().children, node);
+        }
+    }
+}
+
+impl<B: Buffer> Iterator for Fragments<B> {
+    type Item = Result<Element, Error>;
+
+    /// Returns the next matching subtree; the iteration stops after the end of
+    /// the document or after an error.
+    fn next(&mut self) -> Option<Result<Element, Error>> {
+        if self.finished {
+            return None;
+        }
+        match self.next_fragment() {
+            Ok(Some((_, element))) => Some(Ok(element)),
+            Ok(None) => { self.finished = true; None }
+            Err(e) => { self.finished = true; Some(Err(e)) }
+        }
+    }
+}
+
+/// Collects namespace mappings in scope, excluding the predefined ones.
+fn in_scope_namespace(context: &ReaderContext) -> Namespace {
+    let mut namespace = Namespace::empty();
+    for (prefix, uri) in context.namespaces().uri_mappings() {
+        match prefix {
+            Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) => {}
+            None if uri.is_empty() => {}
+            _ => { namespace.put(prefix, uri); }
+        }
+    }
+    namespace
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Buffer, ByRefWriter};
+
+    use reader::EventReader;
+    use writer::EventWriter;
+    use dom::{Element, Node, Text};
+    use super::Fragments;
+
+    const FEED: &'static str = r#"<feed xmlns="urn:feed" xmlns:x="urn:ext">
+        <title>Feed</title>
+        <record id="1"><x:value>one</x:value></record>
+        <other><record id="2"><x:value>two</x:value></record></other>
+    </feed>"#;
+
+    fn fragments<B: Buffer>(iter: Fragments<B>) -> Vec<Element> {
+        iter.map(|r| r.ok().expect("Cannot read fragment")).collect()
+    }
+
+    #[test]
+    fn selects_records_by_name() {
+        let records = fragments(
+            Fragments::with_name(EventReader::new_from_str_slice(FEED), Some("urn:feed"), "record").unwrap()
+        );
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].attribute(None, "id"), Some("2"));
+
+        let value = records[0].child_named(Some("urn:ext"), "value").unwrap();
+        assert!(value.children == vec![Node::Text(Text("one".to_string()))]);
+        // namespace declarations from the ancestors are inherited
+        assert_eq!(records[0].namespace.get(&None), Some("urn:feed"));
+        assert_eq!(records[0].namespace.get(&Some("x".to_string())), Some("urn:ext"));
+        assert!(value.namespace.is_empty());
+    }
+
+    #[test]
+    fn selects_records_by_pattern() {
+        // unprefixed names in patterns match elements without a namespace
+        let data = FEED.replace(r#"xmlns="urn:feed" "#, "");
+        let records = fragments(
+            Fragments::with_pattern(EventReader::new_from_str_slice(data.as_slice()), "/feed/record").unwrap()
+        );
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].attribute(None, "id"), Some("1"));
+    }
+
+    #[test]
+    fn selects_records_without_namespace() {
+        let data = r#"<a><record id="1" xmlns="urn:x"/><record id="2"/></a>"#;
+        let records = fragments(
+            Fragments::with_name(EventReader::new_from_str_slice(data), None, "record").unwrap()
+        );
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].attribute(None, "id"), Some("2"));
+
+        let records = fragments(
+            Fragments::with_name(EventReader::new_from_str_slice(FEED), None, "record").unwrap()
+        );
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn fragments_serialize_standalone() {
+        let records = fragments(
+            Fragments::with_name(EventReader::new_from_str_slice(FEED), Some("urn:feed"), "record").unwrap()
+        );
+
+        let mut output = Vec::new();
+        {
+            let mut writer = EventWriter::new(output.by_ref());
+            records[1].write_to(&mut writer).ok().expect("Cannot write fragment");
+        }
+
+        let mut reparsed = fragments(
+            Fragments::with_name(EventReader::new_from_bytes(output), Some("urn:feed"), "record").unwrap()
+        );
+        assert_eq!(reparsed.len(), 1);
+        let record = reparsed.pop().unwrap();
+        assert_eq!(record.child_named(Some("urn:ext"), "value").unwrap().text_content().as_slice(), "two");
+    }
+}
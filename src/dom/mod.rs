@@ -0,0 +1,562 @@
+//! Contains a simple in-memory tree representation of XML documents.
+//!
+//! A `Document` is built from events of an `EventReader` and can be written back
+//! through an `EventWriter`. Element names, attributes and namespace declarations
+//! are kept exactly as the parser reports them, so prefixes and `xmlns` attributes
+//! are preserved when the document is serialized again.
+//!
+//! For large documents which are only queried, see `CompactDocument`; to materialize
+//! only selected subtrees of a stream, see `Fragments`.
+
+use std::mem;
+use std::slice;
+use std::io::Buffer;
+
+use common::{Error, XmlVersion};
+use name::OwnedName;
+use attribute::OwnedAttribute;
+use namespace::Namespace;
+
+use reader::EventReader;
+use reader::events::XmlEvent as ReaderEvent;
+use writer::{EventWriter, EventWriterResult};
+use writer::events::XmlEvent as WriterEvent;
+
+pub use self::navigation::NodeRef;
+pub use self::compact::CompactDocument;
+pub use self::fragments::Fragments;
+
+pub mod navigation;
+pub mod compact;
+pub mod xpath;
+pub mod fragments;
+
+/// An XML document.
+///
+/// Children of a document are the root element and the comments and processing
+/// instructions which surround it.
+#[derive(Clone, PartialEq)]
+pub struct Document {
+    /// XML version from the document declaration.
+    pub version: XmlVersion,
+
+    /// Document encoding from the document declaration.
+    pub encoding: String,
+
+    /// Standalone flag from the document declaration.
+    pub standalone: Option<bool>,
+
+    /// Top-level nodes of the document.
+    pub children: Vec<Node>
+}
+
+/// A node of a document tree.
+#[derive(Clone, PartialEq)]
+pub enum Node {
+    /// An element with its subtree.
+    Element(Element),
+
+    /// Character data.
+    Text(Text),
+
+    /// A CDATA section.
+    CData(CData),
+
+    /// A comment.
+    Comment(Comment),
+
+    /// A processing instruction.
+    ProcessingInstruction(ProcessingInstruction)
+}
+
+/// An XML element.
+#[derive(Clone, PartialEq)]
+pub struct Element {
+    /// Qualified name of the element.
+    pub name: OwnedName,
+
+    /// Attributes of the element, excluding namespace declarations.
+    pub attributes: Vec<OwnedAttribute>,
+
+    /// Namespace declarations made on this element.
+    pub namespace: Namespace,
+
+    /// Child nodes of the element.
+    pub children: Vec<Node>
+}
+
+/// Character data, unescaped.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Text(pub String);
+
+/// Contents of a CDATA section.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct CData(pub String);
+
+/// Contents of a comment.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Comment(pub String);
+
+/// A processing instruction.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct ProcessingInstruction {
+    /// Processing instruction target.
+    pub name: String,
+
+    /// Processing instruction content.
+    pub data: Option<String>
+}
+
+impl Document {
+    /// Creates a document with the given root element and a default declaration.
+    pub fn new(root: Element) -> Document {
+        Document {
+            version: XmlVersion::Version10,
+            encoding: "UTF-8".to_string(),
+            standalone: None,
+            children: vec![Node::Element(root)]
+        }
+    }
+
+    /// Reads a whole document from the given reader.
+    ///
+    /// Whitespace events are stored as text nodes, so the layout of the document
+    /// is preserved. Parser configuration affects the resulting tree; for example,
+    /// comments are only present in the tree if the parser does not ignore them.
+    pub fn from_reader<B: Buffer>(reader: &mut EventReader<B>) -> Result<Document, Error> {
+        let mut document = Document {
+            version: XmlVersion::Version10,
+            encoding: "UTF-8".to_string(),
+            standalone: None,
+            children: Vec::new()
+        };
+        // elements which are currently open
+        let mut stack: Vec<Element> = Vec::new();
+
+        loop {
+            let node = match reader.next() {
+                ReaderEvent::StartDocument { version, encoding, standalone } => {
+                    document.version = version;
+                    document.encoding = encoding;
+                    document.standalone = standalone;
+                    continue;
+                }
+
+                ReaderEvent::EndDocument => return Ok(document),
+
+                ReaderEvent::StartElement { name, attributes, namespace } => {
+                    stack.push(Element {
+                        name: name,
+                        attributes: attributes,
+                        namespace: namespace,
+                        children: Vec::new()
+                    });
+                    continue;
+                }
+
+                ReaderEvent::EndElement { .. } => Node::Element(stack.pop().unwrap()),
+
+                ReaderEvent::ProcessingInstruction { name, data } =>
+                    Node::ProcessingInstruction(ProcessingInstruction { name: name, data: data }),
+
+                ReaderEvent::Characters(data) | ReaderEvent::Whitespace(data) => Node::Text(Text(data)),
+
+                ReaderEvent::CData(data) => Node::CData(CData(data)),
+
+                ReaderEvent::Comment(data) => Node::Comment(Comment(data)),
+
+                ReaderEvent::Error(e) => return Err(e)
+            };
+
+            match stack.last_mut() {
+                Some(parent) => push_node(&mut parent.children, node),
+                None => push_node(&mut document.children, node)
+            }
+        }
+    }
+
+    /// Returns the root element of the document.
+    ///
+    /// Panics if the document does not contain an element.
+    pub fn root(&self) -> &Element {
+        for child in self.children.iter() {
+            if let Node::Element(ref e) = *child {
+                return e;
+            }
+        }
+        panic!("Document does not contain a root element")
+    }
+
+    /// Returns the root element of the document for modification.
+    ///
+    /// Panics if the document does not contain an element.
+    pub fn root_mut(&mut self) -> &mut Element {
+        for child in self.children.iter_mut() {
+            if let Node::Element(ref mut e) = *child {
+                return e;
+            }
+        }
+        panic!("Document does not contain a root element")
+    }
+
+    /// Returns a reference to the document node, which can be used to navigate the tree.
+    #[inline]
+    pub fn document_node(&self) -> NodeRef {
+        NodeRef::document_node(self)
+    }
+
+    /// Returns a reference to the root element node, which can be used to navigate the tree.
+    ///
+    /// Panics if the document does not contain an element.
+    pub fn root_node(&self) -> NodeRef {
+        self.document_node().children()
+            .find(|n| n.element().is_some())
+            .expect("Document does not contain a root element")
+    }
+
+    /// Writes the document, including the document declaration, to the given writer.
+    pub fn write_to<W: Writer>(&self, writer: &mut EventWriter<W>) -> EventWriterResult<()> {
+        try!(writer.write(WriterEvent::StartDocument {
+            version: self.version,
+            encoding: Some(self.encoding.as_slice()),
+            standalone: self.standalone
+        }));
+        for child in self.children.iter() {
+            try!(child.write_to(writer));
+        }
+        Ok(())
+    }
+}
+
+/// Appends a node to a list of children, merging adjacent text nodes.
+fn push_node(children: &mut Vec<Node>, node: Node) {
+    if let Node::Text(Text(ref data)) = node {
+        if let Some(&mut Node::Text(Text(ref mut last))) = children.last_mut() {
+            last.push_str(data.as_slice());
+            return;
+        }
+    }
+    children.push(node);
+}
+
+impl Node {
+    /// Writes this node with its subtree to the given writer.
+    pub fn write_to<W: Writer>(&self, writer: &mut EventWriter<W>) -> EventWriterResult<()> {
+        match *self {
+            Node::Element(ref e) => e.write_to(writer),
+            Node::Text(Text(ref data)) => writer.write(WriterEvent::Characters(data.as_slice())),
+            Node::CData(CData(ref data)) => writer.write(WriterEvent::CData(data.as_slice())),
+            Node::Comment(Comment(ref data)) => writer.write(WriterEvent::Comment(data.as_slice())),
+            Node::ProcessingInstruction(ProcessingInstruction { ref name, ref data }) =>
+                writer.write(WriterEvent::ProcessingInstruction {
+                    name: name.as_slice(),
+                    data: data.as_ref().map(|d| d.as_slice())
+                })
+        }
+    }
+
+    /// Returns the text content of this node.
+    ///
+    /// For elements it is the concatenation of all text and CDATA descendants; for
+    /// text and CDATA nodes it is their content; for comments and processing instructions
+    /// it is their data.
+    pub fn text_content(&self) -> String {
+        match *self {
+            Node::Element(ref e) => e.text_content(),
+            Node::Text(Text(ref data)) | Node::CData(CData(ref data)) |
+            Node::Comment(Comment(ref data)) => data.clone(),
+            Node::ProcessingInstruction(ref pi) => pi.data.clone().unwrap_or(String::new())
+        }
+    }
+
+    /// Returns the element contained in this node, if it is an element node.
+    #[inline]
+    pub fn as_element(&self) -> Option<&Element> {
+        match *self {
+            Node::Element(ref e) => Some(e),
+            _ => None
+        }
+    }
+
+    /// Returns the element contained in this node for modification, if it is an element node.
+    #[inline]
+    pub fn as_element_mut(&mut self) -> Option<&mut Element> {
+        match *self {
+            Node::Element(ref mut e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl Element {
+    /// Creates an element with the given name and without attributes, namespace
+    /// declarations or children.
+    #[inline]
+    pub fn new(name: OwnedName) -> Element {
+        Element {
+            name: name,
+            attributes: Vec::new(),
+            namespace: Namespace::empty(),
+            children: Vec::new()
+        }
+    }
+
+    /// Returns the value of an attribute with the given namespace URI and local name.
+    ///
+    /// Prefixes are not taken into account. `None` namespace means no namespace, which is
+    /// the case for unprefixed attributes.
+    pub fn attribute(&self, namespace: Option<&str>, local_name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|a| a.name.has_expanded_name(namespace, local_name))
+            .map(|a| a.value.as_slice())
+    }
+
+    /// Sets the value of an attribute.
+    ///
+    /// If an attribute with the same namespace URI and local name is present, its value
+    /// is replaced and its prefix is kept; otherwise a new attribute is added. Returns
+    /// the previous value of the attribute.
+    pub fn set_attribute(&mut self, name: OwnedName, value: String) -> Option<String> {
+        let existing = self.attributes.iter().position(|a| {
+            a.name.has_expanded_name(name.namespace_as_ref(), name.local_name.as_slice())
+        });
+        match existing {
+            Some(i) => Some(mem::replace(&mut self.attributes[i].value, value)),
+            None => {
+                self.attributes.push(OwnedAttribute::new(name, value));
+                None
+            }
+        }
+    }
+
+    /// Removes an attribute with the given namespace URI and local name and returns it.
+    pub fn remove_attribute(&mut self, namespace: Option<&str>, local_name: &str) -> Option<OwnedAttribute> {
+        match self.attributes.iter().position(|a| a.name.has_expanded_name(namespace, local_name)) {
+            Some(i) => Some(self.attributes.remove(i)),
+            None => None
+        }
+    }
+
+    /// Returns an iterator over child elements with the given namespace URI and local name.
+    #[inline]
+    pub fn children_named<'a>(&'a self, namespace: Option<&'a str>, local_name: &'a str) -> ChildrenNamed<'a> {
+        ChildrenNamed {
+            children: self.children.iter(),
+            namespace: namespace,
+            local_name: local_name
+        }
+    }
+
+    /// Returns the first child element with the given namespace URI and local name.
+    #[inline]
+    pub fn child_named<'a>(&'a self, namespace: Option<&'a str>, local_name: &'a str) -> Option<&'a Element> {
+        self.children_named(namespace, local_name).next()
+    }
+
+    /// Returns an iterator over child elements, skipping other kinds of nodes.
+    #[inline]
+    pub fn child_elements<'a>(&'a self) -> ChildElements<'a> {
+        ChildElements { children: self.children.iter() }
+    }
+
+    /// Appends a node to the end of the list of children.
+    #[inline]
+    pub fn append_child(&mut self, node: Node) {
+        self.children.push(node);
+    }
+
+    /// Inserts a node into the list of children at the given position.
+    ///
+    /// Panics if `index` is greater than the number of children.
+    #[inline]
+    pub fn insert_child(&mut self, index: usize, node: Node) {
+        self.children.insert(index, node);
+    }
+
+    /// Removes a child at the given position and returns it.
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn remove_child(&mut self, index: usize) -> Node {
+        self.children.remove(index)
+    }
+
+    /// Replaces a child at the given position with another node and returns the old child.
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn replace_child(&mut self, index: usize, node: Node) -> Node {
+        mem::replace(&mut self.children[index], node)
+    }
+
+    /// Returns the concatenation of all text and CDATA nodes in the subtree of this element.
+    pub fn text_content(&self) -> String {
+        let mut result = String::new();
+        self.collect_text(&mut result);
+        result
+    }
+
+    fn collect_text(&self, result: &mut String) {
+        for child in self.children.iter() {
+            match *child {
+                Node::Element(ref e) => e.collect_text(result),
+                Node::Text(Text(ref data)) | Node::CData(CData(ref data)) => result.push_str(data.as_slice()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes this element with its subtree to the given writer.
+    pub fn write_to<W: Writer>(&self, writer: &mut EventWriter<W>) -> EventWriterResult<()> {
+        try!(writer.write(WriterEvent::StartElement {
+            name: self.name.borrow(),
+            attributes: self.attributes.iter().map(|a| a.borrow()).collect(),
+            namespace: &self.namespace
+        }));
+        for child in self.children.iter() {
+            try!(child.write_to(writer));
+        }
+        writer.write(WriterEvent::EndElement { name: Some(self.name.borrow()) })
+    }
+}
+
+/// An iterator over child elements of an element.
+pub struct ChildElements<'a> {
+    children: slice::Iter<'a, Node>
+}
+
+impl<'a> Iterator for ChildElements<'a> {
+    type Item = &'a Element;
+
+    fn next(&mut self) -> Option<&'a Element> {
+        for child in self.children.by_ref() {
+            if let Node::Element(ref e) = *child {
+                return Some(e);
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over child elements with a particular expanded name.
+pub struct ChildrenNamed<'a> {
+    children: slice::Iter<'a, Node>,
+    namespace: Option<&'a str>,
+    local_name: &'a str
+}
+
+impl<'a> Iterator for ChildrenNamed<'a> {
+    type Item = &'a Element;
+
+    fn next(&mut self) -> Option<&'a Element> {
+        for child in self.children.by_ref() {
+            if let Node::Element(ref e) = *child {
+                if e.name.has_expanded_name(self.namespace, self.local_name) {
+                    return Some(e);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{File, BufferedReader, ByRefWriter};
+
+    use name::OwnedName;
+    use reader::EventReader;
+    use writer::EventWriter;
+    use super::{Document, Node, Text};
+
+    fn read_sample(path: &str) -> Document {
+        let file = File::open(&Path::new(path));
+        let mut reader = EventReader::new(BufferedReader::new(file));
+        match Document::from_reader(&mut reader) {
+            Ok(doc) => doc,
+            Err(e) => panic!("Cannot parse {}: {:?}", path, e)
+        }
+    }
+
+    #[test]
+    fn builds_tree_with_namespaces() {
+        let doc = read_sample("data/sample_2.xml");
+        let root = doc.root();
+
+        assert_eq!(root.name.local_name.as_slice(), "data");
+        assert_eq!(root.name.prefix_as_ref(), Some("p"));
+        assert_eq!(root.name.namespace_as_ref(), Some("urn:example:namespace"));
+        assert_eq!(root.namespace.get(&Some("d".to_string())), Some("urn:example:double"));
+
+        let datum = root.children.iter().filter_map(|n| n.as_element()).next().unwrap();
+        assert_eq!(datum.attributes.len(), 1);
+        assert!(datum.namespace.is_empty());
+
+        let name = datum.children.iter().filter_map(|n| n.as_element()).next().unwrap();
+        assert!(name.children == vec![Node::Text(Text("Name".to_string()))]);
+    }
+
+    #[test]
+    fn round_trip_preserves_tree() {
+        for path in ["data/sample_1.xml", "data/sample_2.xml"].iter() {
+            let doc = read_sample(*path);
+
+            let mut output = Vec::new();
+            {
+                let mut writer = EventWriter::new(output.by_ref());
+                doc.write_to(&mut writer).ok().expect("Cannot write document");
+            }
+
+            let mut reader = EventReader::new_from_bytes(output);
+            let doc2 = Document::from_reader(&mut reader).ok().expect("Cannot parse written document");
+            assert!(doc == doc2, "Documents differ after round trip: {}", path);
+        }
+    }
+
+    #[test]
+    fn namespace_aware_lookups() {
+        let doc = read_sample("data/sample_2.xml");
+        let datum = doc.root().child_named(Some("urn:example:namespace"), "datum").unwrap();
+        assert_eq!(datum.attribute(None, "id"), Some("34"));
+
+        let args: Vec<String> = datum.children_named(Some("urn:example:double"), "arg")
+            .map(|e| e.text_content()).collect();
+        assert_eq!(args, vec!["0.3".to_string(), "0.2".to_string()]);
+        assert!(datum.child_named(None, "arg").is_none());
+        assert_eq!(datum.child_elements().count(), 8);
+    }
+
+    #[test]
+    fn mutation() {
+        let mut reader = EventReader::new_from_str_slice(
+            "<a xmlns:p=\"urn:p\" p:x=\"1\"><b>one</b><c/>two</a>"
+        );
+        let mut doc = Document::from_reader(&mut reader).ok().unwrap();
+        let root = doc.root_mut();
+
+        assert_eq!(root.attribute(Some("urn:p"), "x"), Some("1"));
+        let old = root.set_attribute(OwnedName::qualified("x", "urn:p", Some("q")), "2".to_string());
+        assert_eq!(old, Some("1".to_string()));
+        assert_eq!(root.attributes[0].name.prefix_as_ref(), Some("p"));
+        assert_eq!(root.set_attribute(OwnedName::local("y"), "3".to_string()), None);
+        assert_eq!(root.attribute(None, "y"), Some("3"));
+        assert!(root.remove_attribute(None, "y").is_some());
+        assert!(root.attribute(None, "y").is_none());
+
+        assert_eq!(root.text_content().as_slice(), "onetwo");
+        let removed = root.remove_child(1);
+        assert_eq!(removed.as_element().unwrap().name.local_name.as_slice(), "c");
+        root.insert_child(0, Node::Text(Text("zero".to_string())));
+        let old = root.replace_child(2, Node::Text(Text("three".to_string())));
+        assert_eq!(old.text_content().as_slice(), "two");
+        assert_eq!(root.text_content().as_slice(), "zeroonethree");
+    }
+
+    #[test]
+    fn new_document_has_root() {
+        let mut doc = Document::new(super::Element::new(OwnedName::local("root")));
+        assert_eq!(doc.root().name.local_name.as_slice(), "root");
+        doc.root_mut().children.push(Node::Text(Text("x".to_string())));
+        assert_eq!(doc.root().children.len(), 1);
+    }
+}
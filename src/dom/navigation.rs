@@ -0,0 +1,302 @@
+//! Contains `NodeRef`, a handle which allows navigating a document tree in all directions.
+//!
+//! Nodes of a `Document` do not store references to their parents. A `NodeRef` remembers
+//! the way from the document node to a node instead, so parents and siblings can be
+//! reached from it cheaply.
+
+use std::mem;
+
+use dom::{Document, Element, Node};
+
+/// A reference to a node of a document, or to the document itself.
+///
+/// Two references are equal if they point to the same node of the same document.
+#[derive(Clone)]
+pub struct NodeRef<'a> {
+    document: &'a Document,
+    // for every ancestor level, the list of siblings and the index in it
+    path: Vec<(&'a [Node], usize)>
+}
+
+impl<'a> PartialEq for NodeRef<'a> {
+    fn eq(&self, other: &NodeRef<'a>) -> bool {
+        self.document as *const Document == other.document as *const Document &&
+        self.path.len() == other.path.len() &&
+        self.path.iter().zip(other.path.iter()).all(|(&(_, i), &(_, j))| i == j)
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    /// Returns a reference to the given document node.
+    #[inline]
+    pub fn document_node(document: &'a Document) -> NodeRef<'a> {
+        NodeRef { document: document, path: Vec::new() }
+    }
+
+    /// Returns the document this node belongs to.
+    #[inline]
+    pub fn document(&self) -> &'a Document {
+        self.document
+    }
+
+    /// Checks whether this reference points to the document node itself.
+    #[inline]
+    pub fn is_document(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    /// Returns the node this reference points to, or `None` for the document node.
+    #[inline]
+    pub fn node(&self) -> Option<&'a Node> {
+        self.path.last().map(|&(siblings, i)| &siblings[i])
+    }
+
+    /// Returns the element this reference points to, if it is an element node.
+    #[inline]
+    pub fn element(&self) -> Option<&'a Element> {
+        self.node().and_then(|n| n.as_element())
+    }
+
+    /// Returns the number of ancestors of this node, excluding the document node.
+    ///
+    /// The root element has depth 1, and the document node has depth 0.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Returns indices of this node and all its ancestors in the lists of their siblings,
+    /// starting from the top-level node.
+    ///
+    /// Comparing these paths lexicographically gives the document order of nodes.
+    pub fn index_path(&self) -> Vec<usize> {
+        self.path.iter().map(|&(_, i)| i).collect()
+    }
+
+    /// Returns child nodes of this node; it is empty for non-element nodes.
+    pub fn child_nodes(&self) -> &'a [Node] {
+        match self.node() {
+            None => self.document.children.as_slice(),
+            Some(&Node::Element(ref e)) => e.children.as_slice(),
+            Some(_) => self.document.children.slice_to(0)
+        }
+    }
+
+    /// Returns the parent of this node; it is the document node for the top-level nodes.
+    /// The document node itself does not have a parent.
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        if self.path.is_empty() {
+            None
+        } else {
+            let mut path = self.path.clone();
+            path.pop();
+            Some(NodeRef { document: self.document, path: path })
+        }
+    }
+
+    /// Returns the child with the given index.
+    pub fn child(&self, index: usize) -> Option<NodeRef<'a>> {
+        let children = self.child_nodes();
+        if index < children.len() {
+            let mut path = self.path.clone();
+            path.push((children, index));
+            Some(NodeRef { document: self.document, path: path })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the first child of this node.
+    #[inline]
+    pub fn first_child(&self) -> Option<NodeRef<'a>> {
+        self.child(0)
+    }
+
+    /// Returns the last child of this node.
+    #[inline]
+    pub fn last_child(&self) -> Option<NodeRef<'a>> {
+        match self.child_nodes().len() {
+            0 => None,
+            n => self.child(n - 1)
+        }
+    }
+
+    /// Returns the sibling which immediately follows this node.
+    pub fn next_sibling(&self) -> Option<NodeRef<'a>> {
+        match self.path.last() {
+            Some(&(siblings, i)) if i + 1 < siblings.len() => Some(self.sibling(i + 1)),
+            _ => None
+        }
+    }
+
+    /// Returns the sibling which immediately precedes this node.
+    pub fn previous_sibling(&self) -> Option<NodeRef<'a>> {
+        match self.path.last() {
+            Some(&(_, i)) if i > 0 => Some(self.sibling(i - 1)),
+            _ => None
+        }
+    }
+
+    fn sibling(&self, index: usize) -> NodeRef<'a> {
+        let mut path = self.path.clone();
+        path.last_mut().unwrap().1 = index;
+        NodeRef { document: self.document, path: path }
+    }
+
+    /// Returns an iterator over children of this node.
+    #[inline]
+    pub fn children(&self) -> Siblings<'a> {
+        Siblings { next: self.first_child() }
+    }
+
+    /// Returns an iterator over siblings which follow this node, in document order.
+    #[inline]
+    pub fn following_siblings(&self) -> Siblings<'a> {
+        Siblings { next: self.next_sibling() }
+    }
+
+    /// Returns an iterator over siblings which precede this node, in reverse document order.
+    #[inline]
+    pub fn preceding_siblings(&self) -> PrecedingSiblings<'a> {
+        PrecedingSiblings { next: self.previous_sibling() }
+    }
+
+    /// Returns an iterator over ancestors of this node, from the parent up to
+    /// the document node.
+    #[inline]
+    pub fn ancestors(&self) -> Ancestors<'a> {
+        Ancestors { next: self.parent() }
+    }
+
+    /// Returns an iterator over descendants of this node in document order,
+    /// not including the node itself.
+    #[inline]
+    pub fn descendants(&self) -> Descendants<'a> {
+        Descendants { depth: self.depth(), next: self.first_child() }
+    }
+}
+
+/// An iterator over a node and its following siblings.
+pub struct Siblings<'a> {
+    next: Option<NodeRef<'a>>
+}
+
+impl<'a> Iterator for Siblings<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        let next = self.next.as_ref().and_then(|n| n.next_sibling());
+        mem::replace(&mut self.next, next)
+    }
+}
+
+/// An iterator over a node and its preceding siblings, in reverse document order.
+pub struct PrecedingSiblings<'a> {
+    next: Option<NodeRef<'a>>
+}
+
+impl<'a> Iterator for PrecedingSiblings<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        let next = self.next.as_ref().and_then(|n| n.previous_sibling());
+        mem::replace(&mut self.next, next)
+    }
+}
+
+/// An iterator over ancestors of a node, from the parent upwards.
+pub struct Ancestors<'a> {
+    next: Option<NodeRef<'a>>
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        let next = self.next.as_ref().and_then(|n| n.parent());
+        mem::replace(&mut self.next, next)
+    }
+}
+
+/// An iterator over descendants of a node in document order.
+pub struct Descendants<'a> {
+    depth: usize,  // depth of the node whose descendants are iterated
+    next: Option<NodeRef<'a>>
+}
+
+impl<'a> Descendants<'a> {
+    /// Returns the node which follows the subtree of the given node, if it is still
+    /// a descendant of the start node.
+    fn following(&self, node: &NodeRef<'a>) -> Option<NodeRef<'a>> {
+        let mut node = node.clone();
+        while node.depth() > self.depth {
+            match node.next_sibling() {
+                Some(sibling) => return Some(sibling),
+                None => node = node.parent().unwrap()
+            }
+        }
+        None
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<NodeRef<'a>> {
+        let current = match mem::replace(&mut self.next, None) {
+            Some(current) => current,
+            None => return None
+        };
+        self.next = match current.first_child() {
+            Some(child) => Some(child),
+            None => self.following(&current)
+        };
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reader::EventReader;
+    use dom::Document;
+
+    fn document() -> Document {
+        let mut reader = EventReader::new_from_str_slice(
+            "<a><b><c/>text<d/></b>tail<e/></a>"
+        );
+        Document::from_reader(&mut reader).ok().expect("Cannot parse document")
+    }
+
+    fn names<'a, I: Iterator<Item=super::NodeRef<'a>>>(it: I) -> Vec<String> {
+        it.map(|n| match n.element() {
+            Some(e) => e.name.local_name.clone(),
+            None => if n.is_document() { "#document".to_string() } else { "#text".to_string() }
+        }).collect()
+    }
+
+    #[test]
+    fn navigates_in_all_directions() {
+        let doc = document();
+        let root = doc.root_node();
+        assert_eq!(root.depth(), 1);
+        assert!(root.parent().unwrap().is_document());
+
+        let b = root.first_child().unwrap();
+        let c = b.first_child().unwrap();
+        let d = b.last_child().unwrap();
+        assert_eq!(c.element().unwrap().name.local_name.as_slice(), "c");
+        assert_eq!(d.element().unwrap().name.local_name.as_slice(), "d");
+        assert!(c.next_sibling().unwrap().next_sibling().unwrap() == d);
+        assert!(d.previous_sibling().unwrap().previous_sibling().unwrap() == c);
+        assert!(c.previous_sibling().is_none());
+        assert!(d.parent().unwrap() == b);
+        assert_eq!(d.index_path(), vec![0, 0, 2]);
+
+        assert_eq!(names(root.children()), vec!["b", "#text", "e"]);
+        assert_eq!(names(d.ancestors()), vec!["b", "a", "#document"]);
+        assert_eq!(names(root.descendants()), vec!["b", "c", "#text", "d", "#text", "e"]);
+        assert_eq!(names(b.descendants()), vec!["c", "#text", "d"]);
+        assert_eq!(names(c.following_siblings()), vec!["#text", "d"]);
+        assert_eq!(names(d.preceding_siblings()), vec!["#text", "c"]);
+    }
+}
@@ -0,0 +1,593 @@
+//! Contains evaluation of parsed XPath expressions.
+
+use std::iter;
+use std::f64;
+use std::num::Float;
+use std::ascii::AsciiExt;
+
+use common::is_whitespace_char;
+use namespace::NS_XML_URI;
+
+use dom::{Node, NodeRef};
+
+use super::{Context, Value, XPathNode, XPathError, namespace_nodes, document_order, string_to_number};
+use super::parser::{Expr, PathStart, Step, Axis, NodeTest, BinaryOp};
+
+type EvalResult<'a> = Result<Value<'a>, XPathError>;
+
+#[inline]
+fn error<T>(msg: String) -> Result<T, XPathError> {
+    Err(XPathError::Evaluation(msg))
+}
+
+fn node_set<'a>(value: Value<'a>, msg: &str) -> Result<Vec<XPathNode<'a>>, XPathError> {
+    match value {
+        Value::NodeSet(nodes) => Ok(nodes),
+        _ => error(msg.to_string())
+    }
+}
+
+/// Returns the tree node of an XPath node, if it is neither the document node nor
+/// an attribute or namespace node.
+#[inline]
+fn tree_node<'a>(node: &XPathNode<'a>) -> Option<&'a Node> {
+    match *node {
+        XPathNode::Tree(ref n) => n.node(),
+        _ => None
+    }
+}
+
+/// Evaluates an expression with the given context node.
+pub fn evaluate<'a>(context: &Context<'a>, expr: &Expr, node: &XPathNode<'a>) -> EvalResult<'a> {
+    context.eval(expr, node, 1, 1)
+}
+
+impl<'a> Context<'a> {
+    /// Evaluates an expression with the given context node, context position and context size.
+    fn eval(&self, expr: &Expr, node: &XPathNode<'a>, position: usize, size: usize) -> EvalResult<'a> {
+        match *expr {
+            Expr::Literal(ref value) => Ok(Value::String(value.clone())),
+
+            Expr::Number(value) => Ok(Value::Number(value)),
+
+            Expr::Variable(ref name) => match self.variables.get(name) {
+                Some(value) => Ok(value.clone()),
+                None => error(format!("Undefined variable: ${}", name))
+            },
+
+            Expr::Negate(ref e) => Ok(Value::Number(-try!(self.eval(&**e, node, position, size)).number())),
+
+            Expr::Function(ref name, ref args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    values.push(try!(self.eval(arg, node, position, size)));
+                }
+                self.call(name.as_slice(), values.as_slice(), node, position, size)
+            }
+
+            Expr::Binary(op, ref left, ref right) => self.binary(op, &**left, &**right, node, position, size),
+
+            Expr::Filter(ref primary, ref predicates) => {
+                let value = try!(self.eval(&**primary, node, position, size));
+                let nodes = try!(node_set(value, "Predicates can only be applied to node sets"));
+                Ok(Value::NodeSet(try!(self.filter(predicates.as_slice(), nodes))))
+            }
+
+            Expr::Path(ref start, ref steps) => {
+                let mut nodes = match *start {
+                    PathStart::Root =>
+                        vec![XPathNode::Tree(NodeRef::document_node(node.tree_node().document()))],
+                    PathStart::Context => vec![node.clone()],
+                    PathStart::Expr(ref e) => {
+                        let value = try!(self.eval(&**e, node, position, size));
+                        try!(node_set(value, "Location steps can only be applied to node sets"))
+                    }
+                };
+                for step in steps.iter() {
+                    nodes = try!(self.step(step, nodes));
+                }
+                Ok(Value::NodeSet(nodes))
+            }
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, left: &Expr, right: &Expr,
+              node: &XPathNode<'a>, position: usize, size: usize) -> EvalResult<'a> {
+        // the right operand of `or` and `and` is not evaluated if the result is already known
+        match op {
+            BinaryOp::Or => return Ok(Value::Boolean(
+                try!(self.eval(left, node, position, size)).boolean() ||
+                try!(self.eval(right, node, position, size)).boolean()
+            )),
+            BinaryOp::And => return Ok(Value::Boolean(
+                try!(self.eval(left, node, position, size)).boolean() &&
+                try!(self.eval(right, node, position, size)).boolean()
+            )),
+            _ => {}
+        }
+
+        let left = try!(self.eval(left, node, position, size));
+        let right = try!(self.eval(right, node, position, size));
+        Ok(match op {
+            BinaryOp::Union => {
+                let mut nodes = try!(node_set(left, "Operands of | must be node sets"));
+                nodes.extend(try!(node_set(right, "Operands of | must be node sets")).into_iter());
+                Value::NodeSet(document_order(nodes))
+            }
+            BinaryOp::Add      => Value::Number(left.number() + right.number()),
+            BinaryOp::Subtract => Value::Number(left.number() - right.number()),
+            BinaryOp::Multiply => Value::Number(left.number() * right.number()),
+            BinaryOp::Divide   => Value::Number(left.number() / right.number()),
+            BinaryOp::Modulo   => Value::Number(left.number() % right.number()),
+            _ => Value::Boolean(compare(op, &left, &right))
+        })
+    }
+
+    fn step(&self, step: &Step, nodes: Vec<XPathNode<'a>>) -> Result<Vec<XPathNode<'a>>, XPathError> {
+        let mut result = Vec::new();
+        for node in nodes.iter() {
+            let mut selected = Vec::new();
+            for candidate in axis_nodes(step.axis, node).into_iter() {
+                if try!(self.matches(&step.test, step.axis, &candidate)) {
+                    selected.push(candidate);
+                }
+            }
+            result.extend(try!(self.filter(step.predicates.as_slice(), selected)).into_iter());
+        }
+        Ok(document_order(result))
+    }
+
+    /// Applies predicates to nodes, which must be in the order of the axis they were
+    /// selected with, or in document order for filter expressions.
+    fn filter(&self, predicates: &[Expr], nodes: Vec<XPathNode<'a>>) -> Result<Vec<XPathNode<'a>>, XPathError> {
+        let mut nodes = nodes;
+        for predicate in predicates.iter() {
+            let size = nodes.len();
+            let mut kept = Vec::with_capacity(size);
+            for (i, node) in nodes.into_iter().enumerate() {
+                let keep = match try!(self.eval(predicate, &node, i + 1, size)) {
+                    Value::Number(n) => n == (i + 1) as f64,
+                    value => value.boolean()
+                };
+                if keep {
+                    kept.push(node);
+                }
+            }
+            nodes = kept;
+        }
+        Ok(nodes)
+    }
+
+    fn matches(&self, test: &NodeTest, axis: Axis, node: &XPathNode<'a>) -> Result<bool, XPathError> {
+        Ok(match *test {
+            NodeTest::Node => true,
+
+            NodeTest::Text => match tree_node(node) {
+                Some(&Node::Text(_)) | Some(&Node::CData(_)) => true,
+                _ => false
+            },
+
+            NodeTest::Comment => match tree_node(node) {
+                Some(&Node::Comment(_)) => true,
+                _ => false
+            },
+
+            NodeTest::ProcessingInstruction(ref target) => match tree_node(node) {
+                Some(&Node::ProcessingInstruction(ref pi)) => target.as_ref().map_or(true, |t| *t == pi.name),
+                _ => false
+            },
+
+            NodeTest::Name(ref prefix, ref local_name) => {
+                // name tests only select nodes of the principal node type of the axis
+                let principal = match (axis, node) {
+                    (Axis::Attribute, &XPathNode::Attribute(..)) => true,
+                    (Axis::Namespace, &XPathNode::Namespace(..)) => true,
+                    (Axis::Attribute, _) | (Axis::Namespace, _) => false,
+                    (_, &XPathNode::Tree(ref n)) => n.element().is_some(),
+                    _ => false
+                };
+                if !principal {
+                    return Ok(false);
+                }
+
+                let namespace = match *prefix {
+                    Some(ref prefix) => Some(try!(self.resolve_prefix(prefix.as_slice()))),
+                    None => None
+                };
+                match node.expanded_name() {
+                    Some((ns, name)) =>
+                        ns == namespace && local_name.as_ref().map_or(true, |l| l.as_slice() == name),
+                    None => false
+                }
+            }
+        })
+    }
+
+    fn call(&self, name: &str, args: &[Value<'a>], node: &XPathNode<'a>,
+            position: usize, size: usize) -> EvalResult<'a> {
+        macro_rules! arity(
+            ($min:expr, $max:expr) => (
+                if args.len() < $min || args.len() > $max {
+                    return error(format!("Wrong number of arguments for {}()", name));
+                }
+            )
+        );
+
+        match name {
+            "last" => {
+                arity!(0, 0);
+                Ok(Value::Number(size as f64))
+            }
+
+            "position" => {
+                arity!(0, 0);
+                Ok(Value::Number(position as f64))
+            }
+
+            "count" => {
+                arity!(1, 1);
+                match args[0] {
+                    Value::NodeSet(ref nodes) => Ok(Value::Number(nodes.len() as f64)),
+                    _ => error("Argument of count() must be a node set".to_string())
+                }
+            }
+
+            "id" => {
+                arity!(1, 1);
+                let values: Vec<String> = match args[0] {
+                    Value::NodeSet(ref nodes) => nodes.iter().map(|n| n.string_value()).collect(),
+                    ref value => vec![value.string()]
+                };
+                let ids: Vec<&str> = values.iter()
+                    .flat_map(|v| v.as_slice().split(|c: char| is_whitespace_char(c)))
+                    .filter(|id| !id.is_empty())
+                    .collect();
+                let document = NodeRef::document_node(node.tree_node().document());
+                Ok(Value::NodeSet(document.descendants()
+                    .filter(|n| match n.element().and_then(|e| e.attribute(Some(NS_XML_URI), "id")) {
+                        Some(id) => ids.contains(&id),
+                        None => false
+                    })
+                    .map(XPathNode::Tree)
+                    .collect()))
+            }
+
+            "local-name" => {
+                arity!(0, 1);
+                let node = try!(node_arg(args, node));
+                Ok(Value::String(node.as_ref()
+                    .and_then(|n| n.expanded_name())
+                    .map(|(_, name)| name.to_string())
+                    .unwrap_or(String::new())))
+            }
+
+            "namespace-uri" => {
+                arity!(0, 1);
+                let node = try!(node_arg(args, node));
+                Ok(Value::String(node.as_ref()
+                    .and_then(|n| n.expanded_name())
+                    .and_then(|(ns, _)| ns)
+                    .map(|ns| ns.to_string())
+                    .unwrap_or(String::new())))
+            }
+
+            "name" => {
+                arity!(0, 1);
+                let node = try!(node_arg(args, node));
+                Ok(Value::String(node.map(|n| n.qualified_name()).unwrap_or(String::new())))
+            }
+
+            "string" => {
+                arity!(0, 1);
+                Ok(Value::String(string_arg(args, 0, node)))
+            }
+
+            "concat" => {
+                arity!(2, args.len());
+                let mut result = String::new();
+                for arg in args.iter() {
+                    result.push_str(arg.string().as_slice());
+                }
+                Ok(Value::String(result))
+            }
+
+            "starts-with" => {
+                arity!(2, 2);
+                Ok(Value::Boolean(args[0].string().as_slice().starts_with(args[1].string().as_slice())))
+            }
+
+            "contains" => {
+                arity!(2, 2);
+                Ok(Value::Boolean(args[0].string().as_slice().contains(args[1].string().as_slice())))
+            }
+
+            "substring-before" => {
+                arity!(2, 2);
+                let (s, pattern) = (args[0].string(), args[1].string());
+                Ok(Value::String(match s.as_slice().find_str(pattern.as_slice()) {
+                    Some(i) => s.as_slice().slice_to(i).to_string(),
+                    None => String::new()
+                }))
+            }
+
+            "substring-after" => {
+                arity!(2, 2);
+                let (s, pattern) = (args[0].string(), args[1].string());
+                Ok(Value::String(match s.as_slice().find_str(pattern.as_slice()) {
+                    Some(i) => s.as_slice().slice_from(i + pattern.len()).to_string(),
+                    None => String::new()
+                }))
+            }
+
+            "substring" => {
+                arity!(2, 3);
+                let s = args[0].string();
+                let start = round(args[1].number());
+                let end = if args.len() == 3 { start + round(args[2].number()) } else { f64::INFINITY };
+                // characters are numbered from 1; NaN bounds select nothing
+                Ok(Value::String(s.as_slice().chars().enumerate()
+                    .filter(|&(i, _)| {
+                        let p = (i + 1) as f64;
+                        p >= start && p < end
+                    })
+                    .map(|(_, c)| c)
+                    .collect()))
+            }
+
+            "string-length" => {
+                arity!(0, 1);
+                Ok(Value::Number(string_arg(args, 0, node).as_slice().chars().count() as f64))
+            }
+
+            "normalize-space" => {
+                arity!(0, 1);
+                let s = string_arg(args, 0, node);
+                let words: Vec<&str> = s.as_slice()
+                    .split(|c: char| is_whitespace_char(c))
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                Ok(Value::String(words.connect(" ")))
+            }
+
+            "translate" => {
+                arity!(3, 3);
+                let from: Vec<char> = args[1].string().as_slice().chars().collect();
+                let to: Vec<char> = args[2].string().as_slice().chars().collect();
+                let mut result = String::new();
+                for c in args[0].string().as_slice().chars() {
+                    match from.iter().position(|&f| f == c) {
+                        Some(i) if i < to.len() => result.push(to[i]),
+                        Some(_) => {}
+                        None => result.push(c)
+                    }
+                }
+                Ok(Value::String(result))
+            }
+
+            "boolean" => {
+                arity!(1, 1);
+                Ok(Value::Boolean(args[0].boolean()))
+            }
+
+            "not" => {
+                arity!(1, 1);
+                Ok(Value::Boolean(!args[0].boolean()))
+            }
+
+            "true" => {
+                arity!(0, 0);
+                Ok(Value::Boolean(true))
+            }
+
+            "false" => {
+                arity!(0, 0);
+                Ok(Value::Boolean(false))
+            }
+
+            "lang" => {
+                arity!(1, 1);
+                let lang = args[0].string().as_slice().to_ascii_lowercase();
+                let mut current = Some(node.tree_node().clone());
+                while let Some(n) = current {
+                    if let Some(value) = n.element().and_then(|e| e.attribute(Some(NS_XML_URI), "lang")) {
+                        let value = value.to_ascii_lowercase();
+                        return Ok(Value::Boolean(
+                            value == lang || value.as_slice().starts_with(format!("{}-", lang).as_slice())
+                        ));
+                    }
+                    current = n.parent();
+                }
+                Ok(Value::Boolean(false))
+            }
+
+            "number" => {
+                arity!(0, 1);
+                Ok(Value::Number(match args.first() {
+                    Some(value) => value.number(),
+                    None => string_to_number(node.string_value().as_slice())
+                }))
+            }
+
+            "sum" => {
+                arity!(1, 1);
+                match args[0] {
+                    Value::NodeSet(ref nodes) => Ok(Value::Number(nodes.iter()
+                        .map(|n| string_to_number(n.string_value().as_slice()))
+                        .fold(0.0, |a, b| a + b))),
+                    _ => error("Argument of sum() must be a node set".to_string())
+                }
+            }
+
+            "floor" => {
+                arity!(1, 1);
+                Ok(Value::Number(args[0].number().floor()))
+            }
+
+            "ceiling" => {
+                arity!(1, 1);
+                Ok(Value::Number(args[0].number().ceil()))
+            }
+
+            "round" => {
+                arity!(1, 1);
+                Ok(Value::Number(round(args[0].number())))
+            }
+
+            _ => error(format!("Unknown function: {}()", name))
+        }
+    }
+}
+
+/// Returns the string value of the argument with the given index, or of the context node
+/// if the argument is absent.
+fn string_arg<'a>(args: &[Value<'a>], index: usize, node: &XPathNode<'a>) -> String {
+    if index < args.len() { args[index].string() } else { node.string_value() }
+}
+
+/// Returns the first node of the node set argument, or the context node if the argument
+/// is absent.
+fn node_arg<'a>(args: &[Value<'a>], node: &XPathNode<'a>) -> Result<Option<XPathNode<'a>>, XPathError> {
+    match args.first() {
+        None => Ok(Some(node.clone())),
+        Some(&Value::NodeSet(ref nodes)) => Ok(nodes.first().map(|n| n.clone())),
+        Some(_) => error("Argument must be a node set".to_string())
+    }
+}
+
+/// Rounds a number as `round()` function does: halves are rounded towards positive infinity.
+fn round(n: f64) -> f64 {
+    if n.is_nan() || n.is_infinite() { n } else { (n + 0.5).floor() }
+}
+
+/// Compares two values according to the rules of XPath comparison operators.
+fn compare(op: BinaryOp, left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (&Value::NodeSet(ref l), &Value::NodeSet(ref r)) => {
+            let right_values: Vec<Value> = r.iter().map(|n| Value::String(n.string_value())).collect();
+            l.iter().any(|n| {
+                let left_value = Value::String(n.string_value());
+                right_values.iter().any(|v| compare_atomic(op, &left_value, v))
+            })
+        }
+        (&Value::NodeSet(_), &Value::Boolean(_)) | (&Value::Boolean(_), &Value::NodeSet(_)) =>
+            compare_atomic(op, &Value::Boolean(left.boolean()), &Value::Boolean(right.boolean())),
+        (&Value::NodeSet(ref l), _) =>
+            l.iter().any(|n| compare_atomic(op, &Value::String(n.string_value()), right)),
+        (_, &Value::NodeSet(ref r)) =>
+            r.iter().any(|n| compare_atomic(op, left, &Value::String(n.string_value()))),
+        _ => compare_atomic(op, left, right)
+    }
+}
+
+/// Compares two values neither of which is a node set.
+fn compare_atomic(op: BinaryOp, left: &Value, right: &Value) -> bool {
+    match op {
+        BinaryOp::Equal | BinaryOp::NotEqual => {
+            let equal = match (left, right) {
+                (&Value::Boolean(_), _) | (_, &Value::Boolean(_)) => left.boolean() == right.boolean(),
+                (&Value::Number(_), _) | (_, &Value::Number(_)) => left.number() == right.number(),
+                _ => left.string() == right.string()
+            };
+            equal == (op == BinaryOp::Equal)
+        }
+        _ => {
+            let (l, r) = (left.number(), right.number());
+            match op {
+                BinaryOp::Less => l < r,
+                BinaryOp::LessEqual => l <= r,
+                BinaryOp::Greater => l > r,
+                BinaryOp::GreaterEqual => l >= r,
+                _ => unreachable!()
+            }
+        }
+    }
+}
+
+/// Returns nodes on the given axis of a node, in the order of the axis: document order for
+/// forward axes and reverse document order for reverse axes.
+fn axis_nodes<'a>(axis: Axis, node: &XPathNode<'a>) -> Vec<XPathNode<'a>> {
+    let mut result = Vec::new();
+    match axis {
+        Axis::SelfAxis => result.push(node.clone()),
+
+        Axis::Child => if let XPathNode::Tree(ref n) = *node {
+            result.extend(n.children().map(XPathNode::Tree));
+        },
+
+        Axis::Descendant | Axis::DescendantOrSelf => {
+            if axis == Axis::DescendantOrSelf {
+                result.push(node.clone());
+            }
+            if let XPathNode::Tree(ref n) = *node {
+                result.extend(n.descendants().map(XPathNode::Tree));
+            }
+        }
+
+        Axis::Parent => result.extend(node.parent().into_iter()),
+
+        Axis::Ancestor | Axis::AncestorOrSelf => {
+            if axis == Axis::AncestorOrSelf {
+                result.push(node.clone());
+            }
+            let mut current = node.parent();
+            while let Some(n) = current {
+                current = n.parent();
+                result.push(n);
+            }
+        }
+
+        Axis::FollowingSibling => if let XPathNode::Tree(ref n) = *node {
+            result.extend(n.following_siblings().map(XPathNode::Tree));
+        },
+
+        Axis::PrecedingSibling => if let XPathNode::Tree(ref n) = *node {
+            result.extend(n.preceding_siblings().map(XPathNode::Tree));
+        },
+
+        Axis::Following => {
+            match *node {
+                XPathNode::Tree(_) => {}
+                // children of an element follow its attribute and namespace nodes
+                _ => result.extend(node.tree_node().descendants().map(XPathNode::Tree))
+            }
+            let mut current = Some(node.tree_node().clone());
+            while let Some(n) = current {
+                for sibling in n.following_siblings() {
+                    result.push(XPathNode::Tree(sibling.clone()));
+                    result.extend(sibling.descendants().map(XPathNode::Tree));
+                }
+                current = n.parent();
+            }
+        }
+
+        Axis::Preceding => {
+            // the element of an attribute or namespace node is its ancestor, so it is
+            // excluded as well
+            let mut current = Some(node.tree_node().clone());
+            while let Some(n) = current {
+                for sibling in n.preceding_siblings() {
+                    let mut subtree: Vec<XPathNode<'a>> = sibling.descendants().map(XPathNode::Tree).collect();
+                    subtree.reverse();
+                    result.extend(subtree.into_iter());
+                    result.push(XPathNode::Tree(sibling));
+                }
+                current = n.parent();
+            }
+        }
+
+        Axis::Attribute => if let XPathNode::Tree(ref n) = *node {
+            if let Some(e) = n.element() {
+                for i in iter::range(0, e.attributes.len()) {
+                    result.push(XPathNode::Attribute(n.clone(), i));
+                }
+            }
+        },
+
+        Axis::Namespace => if let XPathNode::Tree(ref n) = *node {
+            if n.element().is_some() {
+                result = namespace_nodes(n);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,520 @@
+//! Contains an implementation of [XPath 1.0][1] over `Document` trees.
+//!
+//! An expression is parsed once with `XPath::parse()` and can then be evaluated any number
+//! of times against nodes of documents:
+//!
+//! ```rust
+//! use xml::reader::EventReader;
+//! use xml::dom::Document;
+//! use xml::dom::xpath::{XPath, XPathNode, Context};
+//!
+//! let mut reader = EventReader::new_from_str_slice("<a><b>1</b><b>2</b></a>");
+//! let doc = Document::from_reader(&mut reader).unwrap();
+//!
+//! let xpath = XPath::parse("sum(/a/b)").unwrap();
+//! let value = xpath.evaluate(&Context::new(), XPathNode::Tree(doc.document_node())).unwrap();
+//! assert_eq!(value.number(), 3.0);
+//! ```
+//!
+//! All axes, node tests and functions of the core function library are supported.
+//! CDATA sections are treated as text nodes, though adjacent text and CDATA nodes are not
+//! merged into one. Since documents do not carry DTD information, `id()` function uses
+//! `xml:id` attributes to find elements.
+//!
+//! Namespace prefixes used in expressions are resolved with the namespace of
+//! the evaluation `Context`; unprefixed names always denote names without a namespace.
+//!
+//! [1]: http://www.w3.org/TR/1999/REC-xpath-19991116/
+
+use std::fmt;
+use std::f64;
+use std::num::Float;
+use std::collections::HashMap;
+
+use namespace::{Namespace, NamespaceIterable, NS_XML_URI, NS_XML_PREFIX};
+
+use dom::{Node, NodeRef};
+
+mod parser;
+mod eval;
+
+/// An error which can occur when parsing or evaluating an XPath expression.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub enum XPathError {
+    /// The expression is malformed; contains the byte offset in the expression at which
+    /// the error was found and an error message.
+    Syntax(usize, String),
+
+    /// The expression cannot be evaluated, e.g. because of an unknown function or
+    /// an undeclared namespace prefix.
+    Evaluation(String)
+}
+
+impl fmt::String for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            XPathError::Syntax(offset, ref msg) => write!(f, "{}: {}", offset, msg),
+            XPathError::Evaluation(ref msg) => write!(f, "{}", msg)
+        }
+    }
+}
+
+/// A node in the XPath data model.
+///
+/// In addition to the nodes of the document tree, XPath has attribute and namespace
+/// nodes which are not represented as `Node`s.
+#[derive(Clone, PartialEq)]
+pub enum XPathNode<'a> {
+    /// The document node or a node of the document tree.
+    Tree(NodeRef<'a>),
+
+    /// An attribute of an element, given by its index in the attribute list.
+    Attribute(NodeRef<'a>, usize),
+
+    /// A namespace node of an element, given by its prefix (empty for the default
+    /// namespace) and namespace URI.
+    Namespace(NodeRef<'a>, String, String)
+}
+
+/// A key which orders nodes in document order.
+type OrderKey = (Vec<usize>, usize, usize, String);
+
+impl<'a> XPathNode<'a> {
+    /// Returns the tree node this node belongs to: the node itself for tree nodes,
+    /// and the owning element for attribute and namespace nodes.
+    #[inline]
+    pub fn tree_node(&self) -> &NodeRef<'a> {
+        match *self {
+            XPathNode::Tree(ref n) | XPathNode::Attribute(ref n, _) |
+            XPathNode::Namespace(ref n, _, _) => n
+        }
+    }
+
+    /// Returns the parent of this node; the parent of an attribute or namespace node
+    /// is its element.
+    pub fn parent(&self) -> Option<XPathNode<'a>> {
+        match *self {
+            XPathNode::Tree(ref n) => n.parent().map(XPathNode::Tree),
+            XPathNode::Attribute(ref n, _) | XPathNode::Namespace(ref n, _, _) =>
+                Some(XPathNode::Tree(n.clone()))
+        }
+    }
+
+    /// Returns the namespace URI and the local name of this node, if it has a name.
+    ///
+    /// Processing instructions are named by their target; namespace nodes are named by
+    /// their prefix, which is empty for the default namespace.
+    pub fn expanded_name(&self) -> Option<(Option<&str>, &str)> {
+        match *self {
+            XPathNode::Tree(ref n) => match n.node() {
+                Some(&Node::Element(ref e)) =>
+                    Some((e.name.namespace_as_ref(), e.name.local_name.as_slice())),
+                Some(&Node::ProcessingInstruction(ref pi)) => Some((None, pi.name.as_slice())),
+                _ => None
+            },
+            XPathNode::Attribute(ref n, i) => {
+                let name = &n.element().unwrap().attributes[i].name;
+                Some((name.namespace_as_ref(), name.local_name.as_slice()))
+            }
+            XPathNode::Namespace(_, ref prefix, _) => Some((None, prefix.as_slice()))
+        }
+    }
+
+    /// Returns the qualified name of this node, as returned by `name()` function.
+    pub fn qualified_name(&self) -> String {
+        match *self {
+            XPathNode::Tree(ref n) => match n.node() {
+                Some(&Node::Element(ref e)) => e.name.to_repr(),
+                Some(&Node::ProcessingInstruction(ref pi)) => pi.name.clone(),
+                _ => String::new()
+            },
+            XPathNode::Attribute(ref n, i) => n.element().unwrap().attributes[i].name.to_repr(),
+            XPathNode::Namespace(_, ref prefix, _) => prefix.clone()
+        }
+    }
+
+    /// Returns the string-value of this node.
+    pub fn string_value(&self) -> String {
+        match *self {
+            XPathNode::Tree(ref n) => match n.node() {
+                Some(node) => node.text_content(),
+                None => n.document().children.iter()
+                    .filter_map(|c| c.as_element())
+                    .map(|e| e.text_content())
+                    .collect()
+            },
+            XPathNode::Attribute(ref n, i) => n.element().unwrap().attributes[i].value.clone(),
+            XPathNode::Namespace(_, _, ref uri) => uri.clone()
+        }
+    }
+
+    fn order_key(&self) -> OrderKey {
+        match *self {
+            XPathNode::Tree(ref n) => (n.index_path(), 0, 0, String::new()),
+            XPathNode::Namespace(ref n, ref prefix, _) => (n.index_path(), 1, 0, prefix.clone()),
+            XPathNode::Attribute(ref n, i) => (n.index_path(), 2, i, String::new())
+        }
+    }
+}
+
+/// Returns namespace nodes of the given element: all in-scope namespace mappings
+/// ordered by prefix.
+fn namespace_nodes<'a>(element: &NodeRef<'a>) -> Vec<XPathNode<'a>> {
+    let mut mappings = vec![(NS_XML_PREFIX.to_string(), NS_XML_URI.to_string())];
+    let mut current = Some(element.clone());
+    while let Some(node) = current {
+        if let Some(e) = node.element() {
+            for (prefix, uri) in e.namespace.uri_mappings() {
+                let prefix = prefix.unwrap_or("");
+                if prefix != "xmlns" && !mappings.iter().any(|&(ref p, _)| p.as_slice() == prefix) {
+                    mappings.push((prefix.to_string(), uri.to_string()));
+                }
+            }
+        }
+        current = node.parent();
+    }
+    // `xmlns=""` undeclares the default namespace
+    mappings.retain(|&(_, ref uri)| !uri.is_empty());
+    mappings.sort();
+    mappings.into_iter().map(|(prefix, uri)| XPathNode::Namespace(element.clone(), prefix, uri)).collect()
+}
+
+/// Sorts nodes in document order and removes duplicates.
+fn document_order<'a>(nodes: Vec<XPathNode<'a>>) -> Vec<XPathNode<'a>> {
+    let mut keyed: Vec<(OrderKey, XPathNode<'a>)> = nodes.into_iter().map(|n| (n.order_key(), n)).collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut result: Vec<XPathNode<'a>> = Vec::with_capacity(keyed.len());
+    let mut last_key: Option<OrderKey> = None;
+    for (key, node) in keyed.into_iter() {
+        if last_key.as_ref() != Some(&key) {
+            result.push(node);
+            last_key = Some(key);
+        }
+    }
+    result
+}
+
+/// A value of an XPath expression.
+#[derive(Clone, PartialEq)]
+pub enum Value<'a> {
+    /// A node set in document order.
+    NodeSet(Vec<XPathNode<'a>>),
+    Boolean(bool),
+    Number(f64),
+    String(String)
+}
+
+impl<'a> Value<'a> {
+    /// Converts the value to a boolean, as `boolean()` function does.
+    pub fn boolean(&self) -> bool {
+        match *self {
+            Value::NodeSet(ref nodes) => !nodes.is_empty(),
+            Value::Boolean(b) => b,
+            Value::Number(n) => n != 0.0 && !n.is_nan(),
+            Value::String(ref s) => !s.is_empty()
+        }
+    }
+
+    /// Converts the value to a number, as `number()` function does.
+    pub fn number(&self) -> f64 {
+        match *self {
+            Value::NodeSet(_) => string_to_number(self.string().as_slice()),
+            Value::Boolean(b) => if b { 1.0 } else { 0.0 },
+            Value::Number(n) => n,
+            Value::String(ref s) => string_to_number(s.as_slice())
+        }
+    }
+
+    /// Converts the value to a string, as `string()` function does.
+    ///
+    /// The string-value of a node set is the string-value of its first node.
+    pub fn string(&self) -> String {
+        match *self {
+            Value::NodeSet(ref nodes) => nodes.first().map(|n| n.string_value()).unwrap_or(String::new()),
+            Value::Boolean(b) => b.to_string(),
+            Value::Number(n) => number_to_string(n),
+            Value::String(ref s) => s.clone()
+        }
+    }
+
+    /// Returns the nodes of a node set value, or `None` if the value is not a node set.
+    pub fn into_nodes(self) -> Option<Vec<XPathNode<'a>>> {
+        match self {
+            Value::NodeSet(nodes) => Some(nodes),
+            _ => None
+        }
+    }
+}
+
+/// Converts a string to a number according to XPath rules: anything but an optional minus
+/// sign followed by a decimal number, surrounded by whitespace, is NaN.
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    let (negative, digits) = if s.starts_with("-") { (true, s.slice_from(1)) } else { (false, s) };
+
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    for c in digits.chars() {
+        match c {
+            '0'...'9' => seen_digit = true,
+            '.' if !seen_dot => seen_dot = true,
+            _ => return f64::NAN
+        }
+    }
+    if !seen_digit {
+        return f64::NAN;
+    }
+
+    let mut normalized = String::new();
+    if digits.starts_with(".") { normalized.push('0'); }
+    normalized.push_str(digits);
+    if digits.ends_with(".") { normalized.push('0'); }
+    match normalized.as_slice().parse::<f64>() {
+        Some(n) => if negative { -n } else { n },
+        None => f64::NAN
+    }
+}
+
+/// Converts a number to a string according to XPath rules.
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity".to_string() } else { "-Infinity".to_string() }
+    } else if n == n.trunc() && n.abs() < 1e15 {
+        // this also turns negative zero into "0"
+        (n as i64).to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+/// An evaluation context: namespace mappings for prefixes used in expressions
+/// and variable bindings.
+pub struct Context<'a> {
+    namespaces: Namespace,
+    variables: HashMap<String, Value<'a>>
+}
+
+impl<'a> Context<'a> {
+    /// Creates a context without namespace mappings and variables.
+    #[inline]
+    pub fn new() -> Context<'a> {
+        Context::with_namespaces(Namespace::empty())
+    }
+
+    /// Creates a context which resolves prefixes with the given namespace.
+    ///
+    /// The default namespace mapping, if it is present, is ignored because unprefixed
+    /// names in XPath 1.0 never belong to a namespace.
+    #[inline]
+    pub fn with_namespaces(namespaces: Namespace) -> Context<'a> {
+        Context {
+            namespaces: namespaces,
+            variables: HashMap::new()
+        }
+    }
+
+    /// Returns the namespace used to resolve prefixes, for modification.
+    #[inline]
+    pub fn namespaces_mut(&mut self) -> &mut Namespace {
+        &mut self.namespaces
+    }
+
+    /// Binds a variable with the given name, replacing the previous binding.
+    ///
+    /// Variables are looked up by their name exactly as it is written in expressions,
+    /// including the prefix.
+    #[inline]
+    pub fn set_variable(&mut self, name: &str, value: Value<'a>) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    fn resolve_prefix(&self, prefix: &str) -> Result<&str, XPathError> {
+        match self.namespaces.get(&Some(prefix.to_string())) {
+            Some(uri) => Ok(uri),
+            None if prefix == NS_XML_PREFIX => Ok(NS_XML_URI),
+            None => Err(XPathError::Evaluation(format!("Undeclared namespace prefix: {}", prefix)))
+        }
+    }
+}
+
+/// A parsed XPath expression.
+#[derive(Clone)]
+pub struct XPath {
+    expr: parser::Expr
+}
+
+impl XPath {
+    /// Parses the given expression.
+    #[inline]
+    pub fn parse(source: &str) -> Result<XPath, XPathError> {
+        parser::parse(source).map(|expr| XPath { expr: expr })
+    }
+
+    /// Evaluates the expression with the given context node.
+    #[inline]
+    pub fn evaluate<'a>(&self, context: &Context<'a>, node: XPathNode<'a>) -> Result<Value<'a>, XPathError> {
+        eval::evaluate(context, &self.expr, &node)
+    }
+
+    /// Evaluates the expression, which must return a node set, with the given context node.
+    pub fn select<'a>(&self, context: &Context<'a>, node: XPathNode<'a>) -> Result<Vec<XPathNode<'a>>, XPathError> {
+        match try!(self.evaluate(context, node)).into_nodes() {
+            Some(nodes) => Ok(nodes),
+            None => Err(XPathError::Evaluation("Expression does not return a node set".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::Float;
+    use std::io::{File, BufferedReader};
+
+    use reader::EventReader;
+    use dom::Document;
+    use super::{XPath, XPathNode, XPathError, Context, Value};
+
+    fn read_sample(path: &str) -> Document {
+        let file = File::open(&Path::new(path));
+        let mut reader = EventReader::new(BufferedReader::new(file));
+        Document::from_reader(&mut reader).ok().expect("Cannot parse document")
+    }
+
+    fn eval<'a>(context: &Context<'a>, doc: &'a Document, expr: &str) -> Value<'a> {
+        let xpath = XPath::parse(expr).ok().expect("Cannot parse expression");
+        match xpath.evaluate(context, XPathNode::Tree(doc.document_node())) {
+            Ok(value) => value,
+            Err(e) => panic!("Cannot evaluate {}: {}", expr, e)
+        }
+    }
+
+    fn string(doc: &Document, expr: &str) -> String {
+        eval(&Context::new(), doc, expr).string()
+    }
+
+    fn number(doc: &Document, expr: &str) -> f64 {
+        eval(&Context::new(), doc, expr).number()
+    }
+
+    fn names(doc: &Document, expr: &str) -> Vec<String> {
+        eval(&Context::new(), doc, expr).into_nodes().expect("Node set expected")
+            .iter().map(|n| n.qualified_name()).collect()
+    }
+
+    #[test]
+    fn location_paths() {
+        let doc = read_sample("data/sample_1.xml");
+
+        assert_eq!(names(&doc, "/project/*"), vec!["libraries", "module", "module"]);
+        assert_eq!(string(&doc, "/project/module[2]/@name"), "module-2");
+        assert_eq!(string(&doc, "//library[last()]/@artifactId"), "junit");
+        assert_eq!(number(&doc, "count(//file)"), 5.0);
+        assert_eq!(number(&doc, "count(//file[@type = 'java'])"), 2.0);
+        assert_eq!(string(&doc, "//file[@name='style.css']/../../@name"), "module-2");
+        assert_eq!(names(&doc, "//files/file[1]/ancestor::*"),
+                   vec!["project", "module", "files", "module", "files"]);
+        assert_eq!(names(&doc, "//file[@type='xml']/preceding-sibling::*"), vec!["file", "file"]);
+        assert_eq!(names(&doc, "(//file)[1]/following::*[position() < 3]"), vec!["file", "file"]);
+        assert!(names(&doc, "(//library)[1]/preceding::*").is_empty());
+        assert_eq!(string(&doc, "name(//library[2]/following::*[1])"), "module");
+        assert_eq!(string(&doc, "(//library)[2]/preceding::*[1]/@version"), "0.1");
+        assert_eq!(names(&doc, "/project/libraries/library[1]/@*"), vec!["groupId", "artifactId", "version"]);
+        assert_eq!(number(&doc, "count(//module/descendant-or-self::module | //module)"), 2.0);
+        assert_eq!(names(&doc, "/project/self::node()/child::libraries"), vec!["libraries"]);
+    }
+
+    #[test]
+    fn namespaces() {
+        let doc = read_sample("data/sample_2.xml");
+
+        let mut context = Context::new();
+        context.namespaces_mut().put(Some("x"), "urn:example:double");
+        context.namespaces_mut().put(Some("p"), "urn:example:namespace");
+
+        let args = eval(&context, &doc, "//x:arg").into_nodes().unwrap();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0].string_value(), "0.3");
+        assert!((eval(&context, &doc, "sum(//p:arg)").number() - 0.11).abs() < 1e-9);
+        assert_eq!(eval(&context, &doc, "local-name(/p:data/p:datum/*[2])").string(), "name");
+        assert_eq!(eval(&context, &doc, "namespace-uri(//x:name)").string(), "urn:example:double");
+        assert_eq!(eval(&context, &doc, "name(//x:name)").string(), "d:name");
+        assert_eq!(eval(&context, &doc, "count(//x:*)").number(), 3.0);
+        assert_eq!(eval(&context, &doc, "//*[local-name() = 'header'][2]/@name").string(), "Header-2");
+
+        let namespaces: Vec<String> = eval(&context, &doc, "//p:datum/namespace::*").into_nodes().unwrap()
+            .iter().map(|n| n.qualified_name()).collect();
+        assert_eq!(namespaces, vec!["d", "h", "p", "xml"]);
+        assert_eq!(eval(&context, &doc, "string(//p:datum/namespace::h)").string(), "urn:example:header");
+
+        match XPath::parse("//q:a").unwrap().evaluate(&context, XPathNode::Tree(doc.document_node())) {
+            Err(XPathError::Evaluation(_)) => {}
+            _ => panic!("Undeclared prefix must be an error")
+        }
+    }
+
+    #[test]
+    fn node_types() {
+        for path in ["data/sample_3.xml", "data/sample_4.xml"].iter() {
+            let doc = read_sample(*path);
+
+            assert_eq!(names(&doc, "/*/*"), vec!["a", "b", "c"]);
+            assert_eq!(string(&doc, "/*/b"), "kkss\" = ddd' >");
+            assert_eq!(string(&doc, "/*/@z"), ">");
+            assert_eq!(number(&doc, "count(/*/comment())"), 0.0);
+            assert_eq!(number(&doc, "count(//text()[contains(., 'CDATA')])"), 1.0);
+            assert_eq!(number(&doc, "count(/*/node()) > count(/*/*)"), 1.0);
+        }
+    }
+
+    #[test]
+    fn functions() {
+        let doc = read_sample("data/sample_1.xml");
+
+        assert_eq!(string(&doc, "concat('a', 1, true())"), "a1true");
+        assert_eq!(string(&doc, "substring('12345', 1.5, 2.6)"), "234");
+        assert_eq!(string(&doc, "substring('12345', 0, 3)"), "12");
+        assert_eq!(string(&doc, "substring('12345', 0 div 0, 3)"), "");
+        assert_eq!(string(&doc, "substring-before('1999/04/01', '/')"), "1999");
+        assert_eq!(string(&doc, "substring-after('1999/04/01', '/')"), "04/01");
+        assert_eq!(string(&doc, "translate('--aaa--', 'abc-', 'ABC')"), "AAA");
+        assert_eq!(string(&doc, "normalize-space(//file[@type='javascript'])"), "JavaScript & program");
+        assert_eq!(number(&doc, "string-length(//file[@type='css']/@name)"), 9.0);
+        assert_eq!(number(&doc, "string-length(normalize-space(//file[@type='css']))"), 28.0);
+        assert_eq!(string(&doc, "boolean(//nothing) or not(starts-with('abc', 'ab'))"), "false");
+        assert_eq!(number(&doc, "floor(-1.5) + ceiling(1.2) + round(2.5) + round(-2.5)"), 1.0);
+        assert_eq!(number(&doc, "7 mod 3 - 7 div 2"), -2.5);
+        assert_eq!(string(&doc, "1 div 0"), "Infinity");
+        assert_eq!(string(&doc, "-1 div 0"), "-Infinity");
+        assert!(number(&doc, "number('1e3')").is_nan());
+        assert_eq!(string(&doc, "number(' -12.50 ')"), "-12.5");
+        assert_eq!(string(&doc, "'10' = 10.0 and //library/@version = 999 and //module/@name != 'x'"), "true");
+        assert_eq!(string(&doc, "//library/@version > 100 and not(//library/@version > 1000)"), "true");
+        assert!((number(&doc, "sum(/project/libraries/library/@version)") - 999.1).abs() < 1e-9);
+        assert!(number(&doc, "sum(//library/@version)").is_nan());
+    }
+
+    #[test]
+    fn variables_and_lang() {
+        let mut reader = EventReader::new_from_str_slice(
+            r#"<a xml:lang="en-US"><b xml:id="x1"/><c xml:lang="de"><d/></c></a>"#
+        );
+        let doc = Document::from_reader(&mut reader).ok().expect("Cannot parse document");
+
+        let mut context = Context::new();
+        context.set_variable("name", Value::String("d".to_string()));
+        context.set_variable("p:n", Value::Number(2.0));
+        assert_eq!(eval(&context, &doc, "count(//*[local-name() = $name])").number(), 1.0);
+        assert_eq!(eval(&context, &doc, "local-name(/a/*[$p:n])").string(), "c");
+        assert_eq!(eval(&context, &doc, "count(//*[lang('en')])").number(), 2.0);
+        assert_eq!(eval(&context, &doc, "local-name(//*[lang('DE')])").string(), "c");
+        assert_eq!(eval(&context, &doc, "local-name(id('x0 x1'))").string(), "b");
+
+        match XPath::parse("$undefined").unwrap().evaluate(&context, XPathNode::Tree(doc.document_node())) {
+            Err(XPathError::Evaluation(_)) => {}
+            _ => panic!("Undefined variable must be an error")
+        }
+    }
+}
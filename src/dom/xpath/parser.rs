@@ -0,0 +1,616 @@
+//! Contains the XPath expression tokenizer and parser.
+//!
+//! The parser follows the grammar from [XPath 1.0 specification][1], including the
+//! disambiguation rules from [section 3.7][2].
+//!
+//! [1]: http://www.w3.org/TR/1999/REC-xpath-19991116/
+//! [2]: http://www.w3.org/TR/1999/REC-xpath-19991116/#exprlex
+
+use common::{is_whitespace_char, is_name_start_char, is_name_char};
+
+use super::{XPathError, string_to_number};
+
+#[derive(Clone, PartialEq, Show)]
+enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DoubleDot,
+    At,
+    Comma,
+    DoubleColon,
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Mod,
+    Div,
+    Multiply,
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    /// A name test: prefix and local name, `None` local name means `*`.
+    NameTest(Option<String>, Option<String>),
+    NodeType(String),
+    FunctionName(String),
+    AxisName(String)
+}
+
+impl Token {
+    /// Checks whether an operator is expected after this token, according to
+    /// the first disambiguation rule.
+    fn ends_operand(&self) -> bool {
+        match *self {
+            Token::At | Token::DoubleColon | Token::LeftParen | Token::LeftBracket | Token::Comma |
+            Token::And | Token::Or | Token::Mod | Token::Div | Token::Multiply |
+            Token::Slash | Token::DoubleSlash | Token::Pipe | Token::Plus | Token::Minus |
+            Token::Equal | Token::NotEqual | Token::Less | Token::LessEqual |
+            Token::Greater | Token::GreaterEqual => false,
+            _ => true
+        }
+    }
+}
+
+/// An axis of a location step.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    SelfAxis
+}
+
+impl Axis {
+    fn from_name(name: &str) -> Option<Axis> {
+        match name {
+            "ancestor"           => Some(Axis::Ancestor),
+            "ancestor-or-self"   => Some(Axis::AncestorOrSelf),
+            "attribute"          => Some(Axis::Attribute),
+            "child"              => Some(Axis::Child),
+            "descendant"         => Some(Axis::Descendant),
+            "descendant-or-self" => Some(Axis::DescendantOrSelf),
+            "following"          => Some(Axis::Following),
+            "following-sibling"  => Some(Axis::FollowingSibling),
+            "namespace"          => Some(Axis::Namespace),
+            "parent"             => Some(Axis::Parent),
+            "preceding"          => Some(Axis::Preceding),
+            "preceding-sibling"  => Some(Axis::PrecedingSibling),
+            "self"               => Some(Axis::SelfAxis),
+            _                    => None
+        }
+    }
+}
+
+/// A node test of a location step.
+#[derive(Clone, PartialEq, Show)]
+pub enum NodeTest {
+    /// `*`, `prefix:*` or a qualified name; contains the prefix and the local name,
+    /// `None` local name means any name.
+    Name(Option<String>, Option<String>),
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>)
+}
+
+/// A location step.
+#[derive(Clone, PartialEq, Show)]
+pub struct Step {
+    pub axis: Axis,
+    pub test: NodeTest,
+    pub predicates: Vec<Expr>
+}
+
+impl Step {
+    fn descendant_or_self() -> Step {
+        Step { axis: Axis::DescendantOrSelf, test: NodeTest::Node, predicates: Vec::new() }
+    }
+}
+
+/// A binary operator.
+#[derive(Copy, Clone, PartialEq, Show)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Union
+}
+
+/// The start of a path expression.
+#[derive(Clone, PartialEq, Show)]
+pub enum PathStart {
+    /// An absolute path starting at the document node.
+    Root,
+    /// A relative path starting at the context node.
+    Context,
+    /// A path starting at the node set returned by a filter expression.
+    Expr(Box<Expr>)
+}
+
+/// A parsed XPath expression.
+#[derive(Clone, PartialEq, Show)]
+pub enum Expr {
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Function(String, Vec<Expr>),
+    Filter(Box<Expr>, Vec<Expr>),
+    Path(PathStart, Vec<Step>)
+}
+
+/// Parses the given XPath expression.
+pub fn parse(source: &str) -> Result<Expr, XPathError> {
+    let tokens = try!(tokenize(source));
+    let mut parser = Parser { tokens: tokens, pos: 0, len: source.len() };
+    let expr = try!(parser.expr());
+    match parser.peek() {
+        None => Ok(expr),
+        Some(t) => parser.error(format!("Unexpected token: {:?}", t))
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, XPathError> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let offset_at = |&: i: usize| if i < chars.len() { chars[i].0 } else { source.len() };
+    let char_at = |&: i: usize| if i < chars.len() { Some(chars[i].1) } else { None };
+    let is_ncname_char = |&: c: char| c != ':' && is_name_char(c);
+    let is_ncname_start_char = |&: c: char| c != ':' && is_name_start_char(c);
+
+    let mut tokens: Vec<(usize, Token)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        let next = char_at(i + 1);
+        let operator_expected = tokens.last().map_or(false, |&(_, ref t)| t.ends_operand());
+
+        macro_rules! single(($t:expr) => ({ i += 1; $t }));
+        macro_rules! double(($second:expr, $t2:expr, $t1:expr) => (
+            if next == Some($second) { i += 2; $t2 } else { i += 1; $t1 }
+        ));
+        macro_rules! error(($($arg:tt)+) => (
+            return Err(XPathError::Syntax(offset, format!($($arg)+)))
+        ));
+
+        let token = match c {
+            _ if is_whitespace_char(c) => { i += 1; continue }
+            '(' => single!(Token::LeftParen),
+            ')' => single!(Token::RightParen),
+            '[' => single!(Token::LeftBracket),
+            ']' => single!(Token::RightBracket),
+            '@' => single!(Token::At),
+            ',' => single!(Token::Comma),
+            '|' => single!(Token::Pipe),
+            '+' => single!(Token::Plus),
+            '-' => single!(Token::Minus),
+            '=' => single!(Token::Equal),
+            '/' => double!('/', Token::DoubleSlash, Token::Slash),
+            '<' => double!('=', Token::LessEqual, Token::Less),
+            '>' => double!('=', Token::GreaterEqual, Token::Greater),
+            '!' if next == Some('=') => { i += 2; Token::NotEqual }
+            ':' if next == Some(':') => { i += 2; Token::DoubleColon }
+            '*' => {
+                i += 1;
+                if operator_expected { Token::Multiply } else { Token::NameTest(None, None) }
+            }
+
+            '"' | '\'' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < chars.len() && chars[end].1 != c {
+                    end += 1;
+                }
+                if end == chars.len() {
+                    error!("Unterminated string literal");
+                }
+                i = end + 1;
+                Token::Literal(source.slice(offset_at(start), offset_at(end)).to_string())
+            }
+
+            '.' if next.map_or(true, |n| !n.is_digit(10)) => double!('.', Token::DoubleDot, Token::Dot),
+
+            _ if c == '.' || c.is_digit(10) => {
+                let start = i;
+                while char_at(i).map_or(false, |c| c.is_digit(10)) { i += 1; }
+                if char_at(i) == Some('.') {
+                    i += 1;
+                    while char_at(i).map_or(false, |c| c.is_digit(10)) { i += 1; }
+                }
+                Token::Number(string_to_number(source.slice(offset_at(start), offset_at(i))))
+            }
+
+            '$' => {
+                i += 1;
+                let start = i;
+                if !char_at(i).map_or(false, |c| is_ncname_start_char(c)) {
+                    error!("Variable name expected");
+                }
+                while char_at(i).map_or(false, |c| is_ncname_char(c)) { i += 1; }
+                if char_at(i) == Some(':') && char_at(i + 1).map_or(false, |c| is_ncname_start_char(c)) {
+                    i += 1;
+                    while char_at(i).map_or(false, |c| is_ncname_char(c)) { i += 1; }
+                }
+                Token::Variable(source.slice(offset_at(start), offset_at(i)).to_string())
+            }
+
+            _ if is_ncname_start_char(c) => {
+                let start = i;
+                while char_at(i).map_or(false, |c| is_ncname_char(c)) { i += 1; }
+                let name = source.slice(offset_at(start), offset_at(i));
+
+                if operator_expected {
+                    match name {
+                        "and" => Token::And,
+                        "or"  => Token::Or,
+                        "mod" => Token::Mod,
+                        "div" => Token::Div,
+                        _     => error!("Operator expected, found {}", name)
+                    }
+                } else if char_at(i) == Some(':') && char_at(i + 1) == Some('*') {
+                    i += 2;
+                    Token::NameTest(Some(name.to_string()), None)
+                } else {
+                    let mut prefix = None;
+                    let mut local = name;
+                    if char_at(i) == Some(':') && char_at(i + 1).map_or(false, |c| is_ncname_start_char(c)) {
+                        i += 1;
+                        let local_start = i;
+                        while char_at(i).map_or(false, |c| is_ncname_char(c)) { i += 1; }
+                        prefix = Some(name);
+                        local = source.slice(offset_at(local_start), offset_at(i));
+                    }
+
+                    let mut j = i;
+                    while char_at(j).map_or(false, |c| is_whitespace_char(c)) { j += 1; }
+                    match (char_at(j), char_at(j + 1)) {
+                        (Some('('), _) => match (prefix, local) {
+                            (None, "node") | (None, "text") | (None, "comment") |
+                            (None, "processing-instruction") => Token::NodeType(local.to_string()),
+                            _ => Token::FunctionName(source.slice(offset, offset_at(i)).to_string())
+                        },
+                        (Some(':'), Some(':')) if prefix.is_none() => Token::AxisName(local.to_string()),
+                        _ => Token::NameTest(prefix.map(|p| p.to_string()), Some(local.to_string()))
+                    }
+                }
+            }
+
+            _ => error!("Unexpected character: {}", c)
+        };
+        tokens.push((offset, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Returns the binary operator denoted by the token at the given precedence level.
+fn operator(level: usize, token: &Token) -> Option<BinaryOp> {
+    match (level, token) {
+        (0, &Token::Or)           => Some(BinaryOp::Or),
+        (1, &Token::And)          => Some(BinaryOp::And),
+        (2, &Token::Equal)        => Some(BinaryOp::Equal),
+        (2, &Token::NotEqual)     => Some(BinaryOp::NotEqual),
+        (3, &Token::Less)         => Some(BinaryOp::Less),
+        (3, &Token::LessEqual)    => Some(BinaryOp::LessEqual),
+        (3, &Token::Greater)      => Some(BinaryOp::Greater),
+        (3, &Token::GreaterEqual) => Some(BinaryOp::GreaterEqual),
+        (4, &Token::Plus)         => Some(BinaryOp::Add),
+        (4, &Token::Minus)        => Some(BinaryOp::Subtract),
+        (5, &Token::Multiply)     => Some(BinaryOp::Multiply),
+        (5, &Token::Div)          => Some(BinaryOp::Divide),
+        (5, &Token::Mod)          => Some(BinaryOp::Modulo),
+        _                         => None
+    }
+}
+
+/// Precedence level of unary expressions, which is higher than that of all binary operators.
+const UNARY_LEVEL: usize = 6;
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize
+}
+
+impl Parser {
+    #[inline]
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).map(|&(_, ref t)| t.clone())
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        let found = match self.tokens.get(self.pos) {
+            Some(&(_, ref t)) => t == token,
+            None => false
+        };
+        if found { self.pos += 1; }
+        found
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), XPathError> {
+        if self.accept(&token) {
+            Ok(())
+        } else {
+            self.error(format!("Expected {:?}", token))
+        }
+    }
+
+    fn error<T>(&self, msg: String) -> Result<T, XPathError> {
+        let offset = self.tokens.get(self.pos).map(|&(o, _)| o).unwrap_or(self.len);
+        Err(XPathError::Syntax(offset, msg))
+    }
+
+    #[inline]
+    fn expr(&mut self) -> Result<Expr, XPathError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, XPathError> {
+        if level == UNARY_LEVEL {
+            return self.unary();
+        }
+
+        let mut left = try!(self.binary(level + 1));
+        loop {
+            let op = match self.peek().and_then(|t| operator(level, &t)) {
+                Some(op) => op,
+                None => return Ok(left)
+            };
+            self.pos += 1;
+            let right = try!(self.binary(level + 1));
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, XPathError> {
+        if self.accept(&Token::Minus) {
+            Ok(Expr::Negate(Box::new(try!(self.unary()))))
+        } else {
+            let mut left = try!(self.path_expr());
+            while self.accept(&Token::Pipe) {
+                let right = try!(self.path_expr());
+                left = Expr::Binary(BinaryOp::Union, Box::new(left), Box::new(right));
+            }
+            Ok(left)
+        }
+    }
+
+    fn path_expr(&mut self) -> Result<Expr, XPathError> {
+        match self.peek() {
+            Some(Token::Variable(_)) | Some(Token::LeftParen) | Some(Token::Literal(_)) |
+            Some(Token::Number(_)) | Some(Token::FunctionName(_)) => {
+                let filter = try!(self.filter_expr());
+                match self.peek() {
+                    Some(Token::Slash) | Some(Token::DoubleSlash) => {
+                        let mut steps = Vec::new();
+                        try!(self.continue_path(&mut steps));
+                        Ok(Expr::Path(PathStart::Expr(Box::new(filter)), steps))
+                    }
+                    _ => Ok(filter)
+                }
+            }
+            _ => self.location_path()
+        }
+    }
+
+    fn filter_expr(&mut self) -> Result<Expr, XPathError> {
+        let primary = try!(self.primary_expr());
+        let predicates = try!(self.predicates());
+        if predicates.is_empty() {
+            Ok(primary)
+        } else {
+            Ok(Expr::Filter(Box::new(primary), predicates))
+        }
+    }
+
+    fn primary_expr(&mut self) -> Result<Expr, XPathError> {
+        match self.peek() {
+            Some(Token::Variable(name)) => { self.pos += 1; Ok(Expr::Variable(name)) }
+            Some(Token::Literal(value)) => { self.pos += 1; Ok(Expr::Literal(value)) }
+            Some(Token::Number(value)) => { self.pos += 1; Ok(Expr::Number(value)) }
+            Some(Token::LeftParen) => {
+                self.pos += 1;
+                let expr = try!(self.expr());
+                try!(self.expect(Token::RightParen));
+                Ok(expr)
+            }
+            Some(Token::FunctionName(name)) => {
+                self.pos += 1;
+                try!(self.expect(Token::LeftParen));
+                let mut args = Vec::new();
+                if !self.accept(&Token::RightParen) {
+                    loop {
+                        args.push(try!(self.expr()));
+                        if self.accept(&Token::RightParen) { break; }
+                        try!(self.expect(Token::Comma));
+                    }
+                }
+                Ok(Expr::Function(name, args))
+            }
+            _ => self.error("Expression expected".to_string())
+        }
+    }
+
+    fn location_path(&mut self) -> Result<Expr, XPathError> {
+        let mut steps = Vec::new();
+        let start = if self.accept(&Token::Slash) {
+            if !self.at_step_start() {
+                return Ok(Expr::Path(PathStart::Root, steps));
+            }
+            steps.push(try!(self.step()));
+            PathStart::Root
+        } else if self.accept(&Token::DoubleSlash) {
+            steps.push(Step::descendant_or_self());
+            steps.push(try!(self.step()));
+            PathStart::Root
+        } else {
+            steps.push(try!(self.step()));
+            PathStart::Context
+        };
+        try!(self.continue_path(&mut steps));
+        Ok(Expr::Path(start, steps))
+    }
+
+    fn continue_path(&mut self, steps: &mut Vec<Step>) -> Result<(), XPathError> {
+        loop {
+            if self.accept(&Token::Slash) {
+                steps.push(try!(self.step()));
+            } else if self.accept(&Token::DoubleSlash) {
+                steps.push(Step::descendant_or_self());
+                steps.push(try!(self.step()));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn at_step_start(&self) -> bool {
+        match self.peek() {
+            Some(Token::Dot) | Some(Token::DoubleDot) | Some(Token::At) | Some(Token::AxisName(_)) |
+            Some(Token::NameTest(_, _)) | Some(Token::NodeType(_)) => true,
+            _ => false
+        }
+    }
+
+    fn step(&mut self) -> Result<Step, XPathError> {
+        if self.accept(&Token::Dot) {
+            return Ok(Step { axis: Axis::SelfAxis, test: NodeTest::Node, predicates: Vec::new() });
+        }
+        if self.accept(&Token::DoubleDot) {
+            return Ok(Step { axis: Axis::Parent, test: NodeTest::Node, predicates: Vec::new() });
+        }
+
+        let axis = match self.peek() {
+            Some(Token::At) => { self.pos += 1; Axis::Attribute }
+            Some(Token::AxisName(name)) => match Axis::from_name(name.as_slice()) {
+                Some(axis) => {
+                    self.pos += 1;
+                    try!(self.expect(Token::DoubleColon));
+                    axis
+                }
+                None => return self.error(format!("Unknown axis: {}", name))
+            },
+            _ => Axis::Child
+        };
+
+        let test = match self.peek() {
+            Some(Token::NameTest(prefix, local)) => {
+                self.pos += 1;
+                NodeTest::Name(prefix, local)
+            }
+            Some(Token::NodeType(node_type)) => {
+                self.pos += 1;
+                try!(self.expect(Token::LeftParen));
+                let test = match node_type.as_slice() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    _ => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            self.pos += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None)
+                    }
+                };
+                try!(self.expect(Token::RightParen));
+                test
+            }
+            _ => return self.error("Node test expected".to_string())
+        };
+
+        let predicates = try!(self.predicates());
+        Ok(Step { axis: axis, test: test, predicates: predicates })
+    }
+
+    fn predicates(&mut self) -> Result<Vec<Expr>, XPathError> {
+        let mut predicates = Vec::new();
+        while self.accept(&Token::LeftBracket) {
+            predicates.push(try!(self.expr()));
+            try!(self.expect(Token::RightBracket));
+        }
+        Ok(predicates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Expr, PathStart, Step, Axis, NodeTest, BinaryOp};
+    use dom::xpath::XPathError;
+
+    fn step(axis: Axis, test: NodeTest) -> Step {
+        Step { axis: axis, test: test, predicates: Vec::new() }
+    }
+
+    fn name(local: &str) -> NodeTest {
+        NodeTest::Name(None, Some(local.to_string()))
+    }
+
+    #[test]
+    fn parses_abbreviated_paths() {
+        assert_eq!(parse("//p:a/@b").unwrap(), Expr::Path(PathStart::Root, vec![
+            step(Axis::DescendantOrSelf, NodeTest::Node),
+            step(Axis::Child, NodeTest::Name(Some("p".to_string()), Some("a".to_string()))),
+            step(Axis::Attribute, name("b"))
+        ]));
+        assert_eq!(parse("../*").unwrap(), Expr::Path(PathStart::Context, vec![
+            step(Axis::Parent, NodeTest::Node),
+            step(Axis::Child, NodeTest::Name(None, None))
+        ]));
+        assert_eq!(parse("/").unwrap(), Expr::Path(PathStart::Root, vec![]));
+    }
+
+    #[test]
+    fn disambiguates_operators() {
+        // `*` and `div` are operators after an operand and names otherwise
+        assert_eq!(parse("div * div").unwrap(), Expr::Binary(
+            BinaryOp::Multiply,
+            Box::new(Expr::Path(PathStart::Context, vec![step(Axis::Child, name("div"))])),
+            Box::new(Expr::Path(PathStart::Context, vec![step(Axis::Child, name("div"))]))
+        ));
+        assert_eq!(parse("1 - -2").unwrap(), Expr::Binary(
+            BinaryOp::Subtract,
+            Box::new(Expr::Number(1.0)),
+            Box::new(Expr::Negate(Box::new(Expr::Number(2.0))))
+        ));
+    }
+
+    #[test]
+    fn reports_error_offsets() {
+        assert_eq!(parse("a[1"), Err(XPathError::Syntax(3, "Expected RightBracket".to_string())));
+        match parse("foo::a") {
+            Err(XPathError::Syntax(0, _)) => {}
+            r => panic!("Unexpected result: {:?}", r)
+        }
+    }
+}
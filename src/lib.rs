@@ -10,6 +10,8 @@ extern crate core;
 #[macro_use]
 extern crate bitflags;
 
+extern crate "rustc-serialize" as rustc_serialize;
+
 pub use reader::EventReader;
 
 pub mod macros;
@@ -20,4 +22,8 @@ pub mod escape;
 pub mod namespace;
 pub mod reader;
 pub mod writer;
+pub mod dom;
+pub mod mapping;
+pub mod de;
+pub mod ser;
 pub mod util;
@@ -24,3 +24,48 @@ macro_rules! gen_setters(
         }
     )+)
 );
+
+/// Writes XML elements and text to an `EventWriter` using a nested, always balanced syntax.
+///
+/// The first argument is a variable holding the writer or a mutable reference to it. Each
+/// element is written as its name, a parenthesized list of attributes and a braced content;
+/// text inside an element is written as a braced expression. Names are identifiers or string literals, which
+/// may contain a prefix. Interpolated values are converted with `to_string()` and escaped
+/// by the writer. The macro evaluates to `EventWriterResult<()>`.
+///
+/// ```rust
+/// # #[macro_use] extern crate xml;
+/// # use xml::writer::{EventWriter, EmitterConfig};
+/// # fn main() {
+/// let config = EmitterConfig::new().write_document_declaration(false);
+/// let mut w = EventWriter::new_with_config(Vec::new(), config);
+/// let (id, sku, qty) = (3u32, "A&B", 2u32);
+/// xml!(w; order(id = id) {
+///     line(sku = sku, "xml:lang" = "en") { {qty} }
+///     note() {}
+/// }).ok().unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! xml(
+    (@name $name:ident) => (stringify!($name));
+    (@name $name:expr) => ($name);
+
+    (@content $w:ident;) => (Ok(()));
+    (@content $w:ident; { $e:expr } $($rest:tt)*) => (
+        match $w.text($e.to_string().as_slice()) {
+            Ok(()) => xml!(@content $w; $($rest)*),
+            Err(e) => Err(e)
+        }
+    );
+    (@content $w:ident; $name:tt ( $($attr:tt = $val:expr),* ) { $($body:tt)* } $($rest:tt)*) => (
+        match $w.element(xml!(@name $name))
+                $(.attr(xml!(@name $attr), $val.to_string().as_slice()))*
+                .children(|$w| xml!(@content $w; $($body)*)) {
+            Ok(()) => xml!(@content $w; $($rest)*),
+            Err(e) => Err(e)
+        }
+    );
+
+    ($w:ident; $($body:tt)*) => (xml!(@content $w; $($body)*))
+);
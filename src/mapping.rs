@@ -0,0 +1,75 @@
+//! Contains the description of how struct fields are mapped to XML.
+//!
+//! `Mapping` is used by the decoder in `de` module and the encoder in `ser` module.
+//! Fields which are not mentioned in a mapping use the default rules of these modules.
+//!
+//! Fields are identified either by their name alone, like `"name"`, or by the name of
+//! the struct and the name of the field, like `"Library.name"`; the latter takes
+//! precedence.
+
+use std::collections::HashMap;
+
+use name::OwnedName;
+
+/// How a struct field is represented in XML.
+#[derive(Clone, PartialEq, Show)]
+pub enum FieldMapping {
+    /// The field is an attribute with the given name.
+    Attribute(OwnedName),
+
+    /// The field is represented by child elements with the given name.
+    Element(OwnedName),
+
+    /// The field is the text content of the element.
+    Text
+}
+
+/// A set of field mappings.
+#[derive(Clone, PartialEq)]
+pub struct Mapping {
+    fields: HashMap<String, FieldMapping>
+}
+
+impl Mapping {
+    /// Returns a mapping which does not override the default rules.
+    ///
+    /// Field mappings are added using builder-like pattern:
+    ///
+    /// ```rust
+    /// use xml::mapping::Mapping;
+    /// use xml::name::OwnedName;
+    ///
+    /// let mapping = Mapping::new()
+    ///     .attribute("Library.group_id", OwnedName::local("groupId"))
+    ///     .element("title", OwnedName::qualified("title", "http://www.w3.org/2005/Atom", Some("atom")))
+    ///     .text("File.content");
+    /// ```
+    #[inline]
+    pub fn new() -> Mapping {
+        Mapping { fields: HashMap::new() }
+    }
+
+    /// Maps a field to an attribute and returns updated mapping.
+    pub fn attribute(mut self, field: &str, name: OwnedName) -> Mapping {
+        self.fields.insert(field.to_string(), FieldMapping::Attribute(name));
+        self
+    }
+
+    /// Maps a field to child elements and returns updated mapping.
+    pub fn element(mut self, field: &str, name: OwnedName) -> Mapping {
+        self.fields.insert(field.to_string(), FieldMapping::Element(name));
+        self
+    }
+
+    /// Maps a field to the text content of the element and returns updated mapping.
+    pub fn text(mut self, field: &str) -> Mapping {
+        self.fields.insert(field.to_string(), FieldMapping::Text);
+        self
+    }
+
+    /// Returns the mapping of the given field of the given struct, if there is one.
+    pub fn get(&self, struct_name: Option<&str>, field: &str) -> Option<&FieldMapping> {
+        let qualified = struct_name.and_then(|s| self.fields.get(&format!("{}.{}", s, field)));
+        qualified.or_else(|| self.fields.get(&field.to_string()))
+    }
+}
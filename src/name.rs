@@ -88,10 +88,46 @@ impl<'a> Name<'a> {
     /// include namespace URI in the result.
     pub fn to_repr(&self) -> String {
         match self.prefix {
-            Some(prefix) => format!("{:?}:{:?}", prefix, self.local_name),
+            Some(prefix) => format!("{}:{}", prefix, self.local_name),
             None => self.local_name.to_owned()
         }
     }
+
+    /// Checks whether this name has the given namespace URI and local name.
+    ///
+    /// Prefixes are not taken into account, so `p:a` and `q:a` are considered the same name
+    /// if both prefixes are bound to the same URI. `None` namespace means no namespace.
+    #[inline]
+    pub fn has_expanded_name(&self, namespace: Option<&str>, local_name: &str) -> bool {
+        self.namespace == namespace && self.local_name == local_name
+    }
+}
+
+/// A value which can be converted into a borrowed qualified name.
+///
+/// Besides `Name` itself, this is implemented for string slices, which are split into
+/// a prefix and a local name at the first colon, e.g. `"p:item"`; the resulting name has
+/// no namespace URI.
+pub trait IntoName<'a> {
+    fn into_name(self) -> Name<'a>;
+}
+
+impl<'a> IntoName<'a> for Name<'a> {
+    #[inline]
+    fn into_name(self) -> Name<'a> { self }
+}
+
+impl<'a> IntoName<'a> for &'a str {
+    fn into_name(self) -> Name<'a> {
+        match self.find(':') {
+            Some(idx) => Name {
+                local_name: self.slice_from(idx + 1),
+                namespace: None,
+                prefix: Some(self.slice_to(idx))
+            },
+            None => Name::local(self)
+        }
+    }
 }
 
 /// An owned variant of `Name`.
@@ -176,6 +212,12 @@ impl OwnedName {
     pub fn to_repr(&self) -> String {
         self.borrow().to_repr()
     }
+
+    /// See `Name::has_expanded_name()` for details.
+    #[inline]
+    pub fn has_expanded_name(&self, namespace: Option<&str>, local_name: &str) -> bool {
+        self.borrow().has_expanded_name(namespace, local_name)
+    }
 }
 
 impl FromStr for OwnedName {
@@ -211,7 +253,7 @@ impl FromStr for OwnedName {
 mod tests {
     use std::str::FromStr;
 
-    use super::OwnedName;
+    use super::{OwnedName, Name, IntoName};
 
     #[test]
     fn test_owned_name_from_str() {
@@ -233,4 +275,10 @@ mod tests {
         assert_eq!("a:".parse(), None::<OwnedName>);
         assert_eq!("a:b:c".parse(), None::<OwnedName>);
     }
+
+    #[test]
+    fn test_str_into_name() {
+        assert_eq!("p:item".into_name(), Name { local_name: "item", namespace: None, prefix: Some("p") });
+        assert_eq!("item".into_name(), Name::local("item"));
+    }
 }
@@ -1,5 +1,20 @@
 //! Contains parser configuration structure.
 
+use std::collections::HashMap;
+
+/// Whitespace handling policy for a particular element.
+///
+/// The policy applies to the whole subtree of the element, unless it is overridden
+/// by a policy or an `xml:space` attribute of a nested element.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum WhitespacePolicy {
+    /// Whitespace is trimmed as if `trim_whitespace` option was set.
+    Trim,
+
+    /// Whitespace is preserved as if `trim_whitespace` option was not set.
+    Preserve
+}
+
 /// Parser configuration structure.
 ///
 /// This structure contains various configuration options which affect
@@ -16,8 +31,22 @@ pub struct ParserConfig {
     ///
     /// This option does not affect CDATA events, unless `cdata_to_characters`
     /// option is also set. In that case CDATA content will also be trimmed.
+    ///
+    /// Whitespace is never trimmed inside elements with `xml:space="preserve"` attribute.
+    /// This option can also be overridden for particular elements with
+    /// `whitespace_policies`.
     pub trim_whitespace: bool,
 
+    /// Whitespace handling policies for particular elements. Default is empty.
+    ///
+    /// Keys are expanded element names, that is, pairs of a namespace URI (`None` means
+    /// no namespace) and a local name; prefixes are not taken into account. A policy
+    /// applies to the subtree of the element and overrides `trim_whitespace` option.
+    /// An `xml:space` attribute takes precedence over a policy for the same element.
+    ///
+    /// Use `whitespace_policy()` method to add policies with builder-like pattern.
+    pub whitespace_policies: HashMap<(Option<String>, String), WhitespacePolicy>,
+
     /// Whether or not should whitespace be converted to characters.
     /// Default is false.
     ///
@@ -68,12 +97,33 @@ impl ParserConfig {
     pub fn new() -> ParserConfig {
         ParserConfig {
             trim_whitespace: false,
+            whitespace_policies: HashMap::new(),
             whitespace_to_characters: false,
             cdata_to_characters: false,
             ignore_comments: true,
             coalesce_characters: true
         }
     }
+
+    /// Sets whitespace handling policy for elements with the given expanded name
+    /// and returns updated config object.
+    ///
+    /// ```rust
+    /// use xml::reader::ParserConfig;
+    /// use xml::reader::config::WhitespacePolicy;
+    ///
+    /// let config = ParserConfig::new()
+    ///     .trim_whitespace(true)
+    ///     .whitespace_policy(None, "pre", WhitespacePolicy::Preserve);
+    /// ```
+    pub fn whitespace_policy(mut self, namespace: Option<&str>, local_name: &str,
+                             policy: WhitespacePolicy) -> ParserConfig {
+        self.whitespace_policies.insert(
+            (namespace.map(|ns| ns.to_string()), local_name.to_string()),
+            policy
+        );
+        self
+    }
 }
 
 gen_setters!(ParserConfig,
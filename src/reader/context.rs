@@ -0,0 +1,227 @@
+//! Contains types which describe the parser context at the current event.
+//!
+//! The context is obtained with `EventReader::context()`. It contains the path of
+//! currently open elements, in-scope namespace mappings and the inherited values of
+//! special `xml:lang`, `xml:space` and `xml:base` attributes.
+
+use std::slice;
+
+use name::OwnedName;
+use namespace::{NamespaceStack, NamespaceIterable};
+use util::resolve_uri;
+
+/// Value of `xml:space` attribute.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum XmlSpace {
+    /// Application default whitespace handling, `xml:space="default"`.
+    Default,
+
+    /// Whitespace should be preserved, `xml:space="preserve"`.
+    Preserve
+}
+
+/// An element which is currently open, together with the values of special
+/// `xml:*` attributes specified directly on it.
+#[derive(Clone, PartialEq)]
+pub struct OpenElement {
+    /// Qualified name of the element.
+    pub name: OwnedName,
+
+    /// Value of `xml:lang` attribute, if it is present on this element.
+    pub lang: Option<String>,
+
+    /// Value of `xml:space` attribute, if it is present on this element and is valid.
+    pub space: Option<XmlSpace>,
+
+    /// Value of `xml:base` attribute, if it is present on this element. It is not resolved
+    /// against the bases of parent elements.
+    pub base: Option<String>
+}
+
+impl OpenElement {
+    /// Creates an element description without any special attributes.
+    #[inline]
+    pub fn new(name: OwnedName) -> OpenElement {
+        OpenElement {
+            name: name,
+            lang: None,
+            space: None,
+            base: None
+        }
+    }
+}
+
+/// A read-only view of the parser context at the last event.
+///
+/// The element which is started or ended by the last `StartElement` or `EndElement` event
+/// is considered open, that is, it is the last element of the path.
+pub struct ReaderContext<'a> {
+    elements: &'a [OpenElement],
+    namespaces: &'a NamespaceStack
+}
+
+impl<'a> ReaderContext<'a> {
+    /// Creates a new context from the element stack and namespace stack of a parser.
+    #[inline]
+    pub fn new(elements: &'a [OpenElement], namespaces: &'a NamespaceStack) -> ReaderContext<'a> {
+        ReaderContext {
+            elements: elements,
+            namespaces: namespaces
+        }
+    }
+
+    /// Returns currently open elements, from the root element to the innermost one.
+    #[inline]
+    pub fn elements(&self) -> &'a [OpenElement] {
+        self.elements
+    }
+
+    /// Returns an iterator over names of open elements, from the root element to the
+    /// innermost one.
+    #[inline]
+    pub fn path(&self) -> Path<'a> {
+        Path { elements: self.elements.iter() }
+    }
+
+    /// Returns the number of open elements.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns the name of the innermost open element.
+    #[inline]
+    pub fn current_element(&self) -> Option<&'a OwnedName> {
+        self.elements.last().map(|e| &e.name)
+    }
+
+    /// Returns the stack of in-scope namespaces.
+    #[inline]
+    pub fn namespaces(&self) -> &'a NamespaceStack {
+        self.namespaces
+    }
+
+    /// Returns the namespace URI bound to the given prefix.
+    ///
+    /// `None` prefix means the default namespace.
+    pub fn namespace_uri(&self, prefix: Option<&str>) -> Option<&'a str> {
+        self.namespaces.uri_mappings()
+            .find(|&(p, _)| p == prefix)
+            .map(|(_, uri)| uri)
+    }
+
+    /// Returns a prefix which is bound to the given namespace URI.
+    ///
+    /// `Some(None)` means that the URI is the default namespace. If several prefixes
+    /// are bound to the URI, any of them may be returned.
+    pub fn prefix_for(&self, uri: &str) -> Option<Option<&'a str>> {
+        self.namespaces.uri_mappings()
+            .find(|&(_, u)| u == uri)
+            .map(|(prefix, _)| prefix)
+    }
+
+    /// Returns the inherited value of `xml:lang` attribute.
+    ///
+    /// An empty value (`xml:lang=""`) resets the language, so `None` is returned for it.
+    pub fn lang(&self) -> Option<&'a str> {
+        self.elements.iter().rev()
+            .filter_map(|e| e.lang.as_ref())
+            .next()
+            .and_then(|lang| if lang.is_empty() { None } else { Some(lang.as_slice()) })
+    }
+
+    /// Returns the inherited value of `xml:space` attribute, or `XmlSpace::Default`
+    /// if no open element specifies it.
+    pub fn space(&self) -> XmlSpace {
+        self.elements.iter().rev()
+            .filter_map(|e| e.space)
+            .next()
+            .unwrap_or(XmlSpace::Default)
+    }
+
+    /// Returns the base URI of the innermost element, as specified by `xml:base`
+    /// attributes of open elements.
+    ///
+    /// Relative `xml:base` values are resolved against the base URI of the parent element.
+    /// `None` is returned if no open element specifies `xml:base`.
+    pub fn base(&self) -> Option<String> {
+        let mut result: Option<String> = None;
+        for base in self.elements.iter().filter_map(|e| e.base.as_ref()) {
+            result = Some(match result {
+                Some(ref current) => resolve_uri(current.as_slice(), base.as_slice()),
+                None => base.clone()
+            });
+        }
+        result
+    }
+}
+
+/// An iterator over names of open elements.
+pub struct Path<'a> {
+    elements: slice::Iter<'a, OpenElement>
+}
+
+impl<'a> Iterator for Path<'a> {
+    type Item = &'a OwnedName;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a OwnedName> {
+        self.elements.next().map(|e| &e.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reader::EventReader;
+    use reader::events::XmlEvent;
+    use super::XmlSpace;
+
+    #[test]
+    fn context_follows_events() {
+        let mut r = EventReader::new_from_str_slice(r#"
+            <a xml:lang="en" xml:base="http://example.com/docs/" xmlns:p="urn:p">
+              <p:b xml:space="preserve" xml:base="guide/">
+                <c xml:lang="" xml:base="../img/x.png"/>
+              </p:b>
+            </a>
+        "#);
+
+        loop {
+            match r.next() {
+                XmlEvent::StartElement { ref name, .. } if name.local_name.as_slice() == "b" => break,
+                XmlEvent::EndDocument | XmlEvent::Error(_) => panic!("<p:b> not found"),
+                _ => {}
+            }
+        }
+        {
+            let ctx = r.context();
+            let path: Vec<&str> = ctx.path().map(|n| n.local_name.as_slice()).collect();
+            assert_eq!(path, vec!["a", "b"]);
+            assert_eq!(ctx.lang(), Some("en"));
+            assert_eq!(ctx.space(), XmlSpace::Preserve);
+            assert_eq!(ctx.base(), Some("http://example.com/docs/guide/".to_string()));
+            assert_eq!(ctx.namespace_uri(Some("p")), Some("urn:p"));
+            assert_eq!(ctx.prefix_for("urn:p"), Some(Some("p")));
+            assert_eq!(ctx.namespace_uri(Some("q")), None);
+        }
+
+        loop {
+            match r.next() {
+                XmlEvent::StartElement { .. } => break,
+                XmlEvent::EndDocument | XmlEvent::Error(_) => panic!("<c> not found"),
+                _ => {}
+            }
+        }
+        {
+            let ctx = r.context();
+            assert_eq!(ctx.depth(), 3);
+            assert_eq!(ctx.lang(), None);
+            assert_eq!(ctx.base(), Some("http://example.com/docs/img/x.png".to_string()));
+        }
+
+        match r.next() {
+            XmlEvent::EndElement { .. } => assert_eq!(r.context().depth(), 3),
+            e => panic!("Unexpected event: {:?}", e)
+        }
+    }
+}
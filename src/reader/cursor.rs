@@ -0,0 +1,270 @@
+//! Contains a cursor-based interface on top of the pull parser.
+//!
+//! `EventCursor` is similar to StAX `XMLStreamReader`: it keeps the current event and
+//! provides convenience methods which are needed by almost every consumer of XML data,
+//! like skipping insignificant whitespace between tags, reading text-only elements or
+//! skipping whole subtrees.
+
+use std::io::Buffer;
+use std::string::ToString;
+
+use common::{Error, is_whitespace_char};
+use name::OwnedName;
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+
+/// A cursor over XML events read by an `EventReader`.
+///
+/// The cursor is positioned before the first event when it is created; use `next()`
+/// or `next_tag()` to move it.
+pub struct EventCursor<B> {
+    reader: EventReader<B>,
+    current: Option<XmlEvent>,
+    depth: usize,
+    pop_depth: bool
+}
+
+impl<B: Buffer> EventCursor<B> {
+    /// Creates a new cursor which reads events from the given reader.
+    #[inline]
+    pub fn new(reader: EventReader<B>) -> EventCursor<B> {
+        EventCursor {
+            reader: reader,
+            current: None,
+            depth: 0,
+            pop_depth: false
+        }
+    }
+
+    /// Returns the underlying reader, consuming the cursor.
+    #[inline]
+    pub fn into_inner(self) -> EventReader<B> {
+        self.reader
+    }
+
+    /// Returns the event the cursor is positioned at, if any.
+    #[inline]
+    pub fn current(&self) -> Option<&XmlEvent> {
+        self.current.as_ref()
+    }
+
+    /// Returns the number of open elements at the current position.
+    ///
+    /// An element is considered open both at its `StartElement` and at its `EndElement`
+    /// event, so the root element has depth 1 at both of them.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the name of the current element if the cursor is positioned at
+    /// `StartElement` or `EndElement` event.
+    pub fn current_name(&self) -> Option<&OwnedName> {
+        match self.current {
+            Some(XmlEvent::StartElement { ref name, .. }) |
+            Some(XmlEvent::EndElement { ref name }) => Some(name),
+            _ => None
+        }
+    }
+
+    /// Returns the value of an attribute of the current element.
+    ///
+    /// The attribute is looked up by its namespace URI and local name; prefixes are not
+    /// taken into account. Returns `None` if the cursor is not positioned at
+    /// `StartElement` event or if there is no such attribute.
+    pub fn attribute_value(&self, namespace: Option<&str>, local_name: &str) -> Option<&str> {
+        match self.current {
+            Some(XmlEvent::StartElement { ref attributes, .. }) =>
+                attributes.iter()
+                    .find(|a| a.name.has_expanded_name(namespace, local_name))
+                    .map(|a| a.value.as_slice()),
+            _ => None
+        }
+    }
+
+    /// Moves the cursor to the next event and returns it.
+    ///
+    /// Parser errors are returned as `Err`; the cursor stays at the error afterwards.
+    pub fn next(&mut self) -> Result<&XmlEvent, Error> {
+        try!(self.advance());
+        Ok(self.current.as_ref().unwrap())
+    }
+
+    /// Moves the cursor to the next `StartElement` or `EndElement` event and returns it.
+    ///
+    /// Whitespace, comments and processing instructions are skipped. It is an error
+    /// to encounter non-whitespace characters or the end of the document.
+    pub fn next_tag(&mut self) -> Result<&XmlEvent, Error> {
+        loop {
+            try!(self.advance());
+            let found = match self.current {
+                Some(XmlEvent::StartElement { .. }) | Some(XmlEvent::EndElement { .. }) => true,
+
+                Some(XmlEvent::StartDocument { .. }) | Some(XmlEvent::Whitespace(_)) |
+                Some(XmlEvent::Comment(_)) | Some(XmlEvent::ProcessingInstruction { .. }) => false,
+
+                Some(XmlEvent::Characters(ref data)) | Some(XmlEvent::CData(ref data))
+                    if data.as_slice().chars().all(is_whitespace_char) => false,
+
+                Some(XmlEvent::Characters(_)) | Some(XmlEvent::CData(_)) =>
+                    return Err(self.error("Expected start or end tag, found non-whitespace characters")),
+
+                _ => return Err(self.error("Expected start or end tag, found end of document"))
+            };
+            if found {
+                return Ok(self.current.as_ref().unwrap());
+            }
+        }
+    }
+
+    /// Reads the text content of a text-only element.
+    ///
+    /// The cursor must be positioned at `StartElement`. Character data, CDATA and
+    /// whitespace are concatenated until the corresponding `EndElement`, at which the
+    /// cursor will be positioned afterwards. Comments and processing instructions are
+    /// skipped; encountering a child element is an error.
+    pub fn element_text(&mut self) -> Result<String, Error> {
+        try!(self.expect_start_element("element_text()"));
+
+        let mut result = String::new();
+        loop {
+            try!(self.advance());
+            match self.current {
+                Some(XmlEvent::Characters(ref data)) | Some(XmlEvent::CData(ref data)) |
+                Some(XmlEvent::Whitespace(ref data)) => result.push_str(data.as_slice()),
+
+                Some(XmlEvent::Comment(_)) | Some(XmlEvent::ProcessingInstruction { .. }) => {}
+
+                Some(XmlEvent::EndElement { .. }) => return Ok(result),
+
+                Some(XmlEvent::StartElement { ref name, .. }) =>
+                    return Err(self.error(format!("Unexpected child element {} in a text-only element", name))),
+
+                _ => return Err(self.error("Unexpected end of document inside an element"))
+            }
+        }
+    }
+
+    /// Skips the current element with all its contents.
+    ///
+    /// The cursor must be positioned at `StartElement`; it will be positioned at
+    /// the corresponding `EndElement` afterwards.
+    pub fn skip_element(&mut self) -> Result<(), Error> {
+        try!(self.expect_start_element("skip_element()"));
+
+        let depth = self.depth;
+        loop {
+            try!(self.advance());
+            match self.current {
+                Some(XmlEvent::EndElement { .. }) if self.depth == depth => return Ok(()),
+                Some(XmlEvent::EndDocument) =>
+                    return Err(self.error("Unexpected end of document inside an element")),
+                _ => {}
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Result<(), Error> {
+        if self.pop_depth {
+            self.pop_depth = false;
+            self.depth -= 1;
+        }
+
+        let event = self.reader.next();
+        match event {
+            XmlEvent::StartElement { .. } => self.depth += 1,
+            XmlEvent::EndElement { .. } => self.pop_depth = true,
+            _ => {}
+        }
+        self.current = Some(event);
+
+        match self.current {
+            Some(XmlEvent::Error(ref e)) => Err(e.clone()),
+            _ => Ok(())
+        }
+    }
+
+    fn expect_start_element(&self, method: &str) -> Result<(), Error> {
+        match self.current {
+            Some(XmlEvent::StartElement { .. }) => Ok(()),
+            _ => Err(self.error(format!("{} must be called at a start element", method)))
+        }
+    }
+
+    #[inline]
+    fn error<S: ToString>(&self, msg: S) -> Error {
+        Error::new(&self.reader, msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reader::EventReader;
+    use reader::events::XmlEvent;
+    use super::EventCursor;
+
+    fn cursor(data: &'static str) -> EventCursor<::std::io::BufReader<'static>> {
+        EventCursor::new(EventReader::new_from_str_slice(data))
+    }
+
+    #[test]
+    fn next_tag_skips_whitespace_and_comments() {
+        let mut c = cursor("<a>\n  <!-- c -->\n  <b x=\"1\" xmlns:p=\"urn:p\" p:y=\"2\"/>\n</a>");
+
+        assert!(c.next_tag().is_ok());
+        assert_eq!(c.current_name().unwrap().local_name.as_slice(), "a");
+        assert_eq!(c.depth(), 1);
+
+        assert!(c.next_tag().is_ok());
+        assert_eq!(c.current_name().unwrap().local_name.as_slice(), "b");
+        assert_eq!(c.depth(), 2);
+        assert_eq!(c.attribute_value(None, "x"), Some("1"));
+        assert_eq!(c.attribute_value(Some("urn:p"), "y"), Some("2"));
+        assert_eq!(c.attribute_value(None, "y"), None);
+
+        match c.next_tag() {
+            Ok(&XmlEvent::EndElement { .. }) => {}
+            _ => panic!("Expected end of <b>")
+        }
+        assert_eq!(c.depth(), 2);
+
+        match c.next_tag() {
+            Ok(&XmlEvent::EndElement { .. }) => {}
+            _ => panic!("Expected end of <a>")
+        }
+        assert_eq!(c.depth(), 1);
+    }
+
+    #[test]
+    fn next_tag_fails_on_text() {
+        let mut c = cursor("<a>text<b/></a>");
+        assert!(c.next_tag().is_ok());
+        assert!(c.next_tag().is_err());
+    }
+
+    #[test]
+    fn element_text_concatenates_text() {
+        let mut c = cursor("<a><b>one <![CDATA[two]]><!-- c --> three</b><c><d/></c></a>");
+        assert!(c.next_tag().is_ok());
+        assert!(c.next_tag().is_ok());
+        assert_eq!(c.element_text().ok(), Some("one two three".to_string()));
+        assert_eq!(c.current_name().unwrap().local_name.as_slice(), "b");
+
+        assert!(c.next_tag().is_ok());
+        assert!(c.element_text().is_err());
+    }
+
+    #[test]
+    fn skip_element_skips_subtree() {
+        let mut c = cursor("<a><b><b/><c>text</c></b><d/></a>");
+        assert!(c.next_tag().is_ok());
+        assert!(c.next_tag().is_ok());
+        assert!(c.skip_element().is_ok());
+        assert_eq!(c.current_name().unwrap().local_name.as_slice(), "b");
+        assert_eq!(c.depth(), 2);
+
+        assert!(c.next_tag().is_ok());
+        assert_eq!(c.current_name().unwrap().local_name.as_slice(), "d");
+    }
+}
@@ -70,7 +70,11 @@ pub enum XmlEvent {
         /// Currently attributes are not checked for duplicates (TODO)
         attributes: Vec<OwnedAttribute>,
 
-        /// Contents of the namespace mapping at this point of the document.
+        /// Namespace declarations made on this element.
+        ///
+        /// Only the mappings declared with `xmlns` attributes of this element are present
+        /// here. The full set of mappings in scope is available through
+        /// `EventReader::namespace_stack()`.
         namespace: Namespace,
     },
 
@@ -180,7 +184,7 @@ impl XmlEvent {
                     namespace: namespace
                 }),
             XmlEvent::EndElement { ref name } =>
-                Some(::writer::events::XmlEvent::EndElement { name: name.borrow() }),
+                Some(::writer::events::XmlEvent::EndElement { name: Some(name.borrow()) }),
             XmlEvent::Comment(ref data) => Some(::writer::events::XmlEvent::Comment(data.as_slice())),
             XmlEvent::CData(ref data) => Some(::writer::events::XmlEvent::CData(data.as_slice())),
             XmlEvent::Characters(ref data) => Some(::writer::events::XmlEvent::Characters(data.as_slice())),
@@ -0,0 +1,443 @@
+//! Contains a streaming matcher which selects subtrees by path patterns.
+//!
+//! `PathMatcher` reads events from an `EventReader` and returns only the events of
+//! subtrees whose root element matches one of the registered patterns. Nothing except
+//! the attributes of open elements is kept in memory, so arbitrarily large documents
+//! can be processed.
+//!
+//! Patterns are a restricted subset of XPath location paths:
+//!
+//! * `/feed/entry/title` --- an absolute path, starting at the root element;
+//! * `//item` --- an element at any depth;
+//! * `ns:order/ns:line` --- a relative path, which matches wherever the path of
+//!   the element ends with the given steps;
+//! * `*` and `ns:*` name tests;
+//! * attribute predicates, `[@type]` and `[@type='x']`, on any step.
+//!
+//! Names are compared by namespace URI and local name, as in XPath. Unprefixed names
+//! in patterns match elements and attributes without a namespace, so an element in
+//! a default namespace is only matched by a prefixed name. A prefix is resolved with
+//! the mappings registered with `PathMatcher::namespace()` or, if it is not registered,
+//! with the namespace mappings in scope in the document.
+
+use std::iter;
+use std::io::Buffer;
+
+use common::{Error, is_whitespace_char, is_name_start_char, is_name_char};
+use attribute::OwnedAttribute;
+use namespace::Namespace;
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+use reader::context::{ReaderContext, OpenElement};
+
+/// A name test with optional prefix; `None` local name means `*`.
+#[derive(Clone, PartialEq, Show)]
+struct NameTest {
+    prefix: Option<String>,
+    local_name: Option<String>
+}
+
+#[derive(Clone, PartialEq, Show)]
+struct AttributeTest {
+    prefix: Option<String>,
+    local_name: String,
+    value: Option<String>
+}
+
+#[derive(Clone, PartialEq, Show)]
+struct PatternStep {
+    /// Whether the step is preceded by `//`.
+    descendant: bool,
+    name: NameTest,
+    attributes: Vec<AttributeTest>
+}
+
+/// A parsed path pattern.
+#[derive(Clone, PartialEq, Show)]
+pub struct PathPattern {
+    absolute: bool,
+    steps: Vec<PatternStep>
+}
+
+impl PathPattern {
+    /// Parses a pattern; see the module documentation for the supported syntax.
+    pub fn parse(source: &str) -> Result<PathPattern, String> {
+        let mut parser = PatternParser { chars: source.chars().collect(), pos: 0 };
+        parser.pattern()
+    }
+
+    /// Checks whether the innermost of the given open elements matches this pattern.
+    ///
+    /// `attributes` contains attributes of the open elements. Prefixes are resolved with
+    /// `bindings` first and with the namespace context of `context` otherwise.
+    fn matches(&self, elements: &[OpenElement], attributes: &[Vec<OwnedAttribute>],
+               bindings: &Namespace, context: &ReaderContext) -> bool {
+        !elements.is_empty() &&
+        self.matches_at(self.steps.len() - 1, elements.len() - 1, elements, attributes, bindings, context)
+    }
+
+    fn matches_at(&self, step: usize, element: usize, elements: &[OpenElement],
+                  attributes: &[Vec<OwnedAttribute>], bindings: &Namespace,
+                  context: &ReaderContext) -> bool {
+        let s = &self.steps[step];
+        if !step_matches(s, &elements[element], attributes[element].as_slice(), bindings, context) {
+            return false;
+        }
+
+        if step == 0 {
+            !self.absolute || s.descendant || element == 0
+        } else if s.descendant {
+            iter::range(0, element).any(|e| self.matches_at(step - 1, e, elements, attributes, bindings, context))
+        } else {
+            element > 0 && self.matches_at(step - 1, element - 1, elements, attributes, bindings, context)
+        }
+    }
+}
+
+/// Checks whether the prefix is bound to the given namespace URI.
+fn prefix_matches(prefix: &str, namespace: Option<&str>, bindings: &Namespace, context: &ReaderContext) -> bool {
+    let uri = match bindings.get(&Some(prefix.to_string())) {
+        Some(uri) => Some(uri),
+        None => context.namespace_uri(Some(prefix))
+    };
+    uri.is_some() && uri == namespace
+}
+
+fn step_matches(step: &PatternStep, element: &OpenElement, attributes: &[OwnedAttribute],
+                bindings: &Namespace, context: &ReaderContext) -> bool {
+    let name = &element.name;
+    let name_matches = match step.name.prefix {
+        Some(ref prefix) => prefix_matches(prefix.as_slice(), name.namespace_as_ref(), bindings, context),
+        None => name.namespace.is_none() || step.name.local_name.is_none()
+    } && step.name.local_name.as_ref().map_or(true, |l| *l == name.local_name);
+
+    name_matches && step.attributes.iter().all(|test| {
+        attributes.iter().any(|attr| {
+            let attr_name_matches = match test.prefix {
+                Some(ref prefix) =>
+                    prefix_matches(prefix.as_slice(), attr.name.namespace_as_ref(), bindings, context),
+                None => attr.name.namespace.is_none()
+            } && attr.name.local_name == test.local_name;
+            attr_name_matches && test.value.as_ref().map_or(true, |v| *v == attr.value)
+        })
+    })
+}
+
+struct PatternParser {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl PatternParser {
+    #[inline]
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&c| c)
+    }
+
+    fn accept(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, is_whitespace_char) {
+            self.pos += 1;
+        }
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!("{} at position {}", msg, self.pos))
+    }
+
+    fn pattern(&mut self) -> Result<PathPattern, String> {
+        let absolute = self.peek() == Some('/');
+        let mut steps = Vec::new();
+        loop {
+            let descendant = if self.accept('/') {
+                self.accept('/')
+            } else if steps.is_empty() {
+                false
+            } else {
+                return self.error("Expected /");
+            };
+            steps.push(try!(self.step(descendant)));
+            if self.peek().is_none() {
+                return Ok(PathPattern { absolute: absolute, steps: steps });
+            }
+        }
+    }
+
+    fn step(&mut self, descendant: bool) -> Result<PatternStep, String> {
+        let name = if self.accept('*') {
+            NameTest { prefix: None, local_name: None }
+        } else {
+            let first = try!(self.ncname());
+            if self.accept(':') {
+                if self.accept('*') {
+                    NameTest { prefix: Some(first), local_name: None }
+                } else {
+                    NameTest { prefix: Some(first), local_name: Some(try!(self.ncname())) }
+                }
+            } else {
+                NameTest { prefix: None, local_name: Some(first) }
+            }
+        };
+
+        let mut attributes = Vec::new();
+        while self.accept('[') {
+            self.skip_whitespace();
+            if !self.accept('@') {
+                return self.error("Expected attribute predicate");
+            }
+            let first = try!(self.ncname());
+            let (prefix, local_name) = if self.accept(':') {
+                (Some(first), try!(self.ncname()))
+            } else {
+                (None, first)
+            };
+            self.skip_whitespace();
+            let value = if self.accept('=') {
+                self.skip_whitespace();
+                Some(try!(self.literal()))
+            } else {
+                None
+            };
+            self.skip_whitespace();
+            if !self.accept(']') {
+                return self.error("Expected ]");
+            }
+            attributes.push(AttributeTest { prefix: prefix, local_name: local_name, value: value });
+        }
+
+        Ok(PatternStep { descendant: descendant, name: name, attributes: attributes })
+    }
+
+    fn ncname(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(c) if c != ':' && is_name_start_char(c) => {}
+            _ => return self.error("Expected name")
+        }
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c == ':' || !is_name_char(c) {
+                break;
+            }
+            name.push(c);
+            self.pos += 1;
+        }
+        Ok(name)
+    }
+
+    fn literal(&mut self) -> Result<String, String> {
+        let quote = match self.peek() {
+            Some(c) if c == '\'' || c == '"' => c,
+            _ => return self.error("Expected string literal")
+        };
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => { self.pos += 1; return Ok(value); }
+                Some(c) => { value.push(c); self.pos += 1; }
+                None => return self.error("Unterminated string literal")
+            }
+        }
+    }
+}
+
+/// A streaming matcher which returns events of subtrees matching path patterns.
+///
+/// When an element matches, all events up to and including its `EndElement` are returned,
+/// tagged with the index of the pattern that matched. Elements nested in a matched subtree
+/// are not matched again. If several patterns match an element, the first registered one
+/// is reported.
+pub struct PathMatcher<B> {
+    reader: EventReader<B>,
+    patterns: Vec<PathPattern>,
+    bindings: Namespace,
+    attributes: Vec<Vec<OwnedAttribute>>,
+    // index of the matched pattern and depth of the matched element
+    matched: Option<(usize, usize)>
+}
+
+impl<B: Buffer> PathMatcher<B> {
+    /// Creates a matcher without patterns, which reads events from the given reader.
+    #[inline]
+    pub fn new(reader: EventReader<B>) -> PathMatcher<B> {
+        PathMatcher {
+            reader: reader,
+            patterns: Vec::new(),
+            bindings: Namespace::empty(),
+            attributes: Vec::new(),
+            matched: None
+        }
+    }
+
+    /// Parses and registers a pattern, returning its index.
+    pub fn add_pattern(&mut self, pattern: &str) -> Result<usize, String> {
+        let pattern = try!(PathPattern::parse(pattern));
+        self.patterns.push(pattern);
+        Ok(self.patterns.len() - 1)
+    }
+
+    /// Binds a prefix used in patterns to a namespace URI.
+    ///
+    /// Bound prefixes take precedence over the namespace mappings of the document.
+    #[inline]
+    pub fn namespace(&mut self, prefix: &str, uri: &str) {
+        self.bindings.force_put(Some(prefix), uri);
+    }
+
+    /// Returns the underlying reader, consuming the matcher.
+    #[inline]
+    pub fn into_inner(self) -> EventReader<B> {
+        self.reader
+    }
+
+    /// Returns the parser context at the last event.
+    #[inline]
+    pub fn context(&self) -> ReaderContext {
+        self.reader.context()
+    }
+
+    /// Reads events until the next one which belongs to a matching subtree and returns it
+    /// together with the index of the pattern that matched.
+    ///
+    /// `None` is returned at the end of the document.
+    pub fn next(&mut self) -> Result<Option<(usize, XmlEvent)>, Error> {
+        loop {
+            let event = self.reader.next();
+            let depth = self.reader.context().depth();
+            match event {
+                XmlEvent::Error(e) => return Err(e),
+
+                XmlEvent::EndDocument => return Ok(None),
+
+                XmlEvent::StartElement { ref attributes, .. } => {
+                    self.attributes.push(attributes.clone());
+                    if self.matched.is_none() {
+                        let context = self.reader.context();
+                        let elements = context.elements();
+                        let attributes = self.attributes.as_slice();
+                        let bindings = &self.bindings;
+                        self.matched = self.patterns.iter()
+                            .position(|p| p.matches(elements, attributes, bindings, &context))
+                            .map(|i| (i, depth));
+                    }
+                }
+
+                XmlEvent::EndElement { .. } => {
+                    self.attributes.pop();
+                }
+
+                _ => {}
+            }
+
+            if let Some((pattern, matched_depth)) = self.matched {
+                if let XmlEvent::EndElement { .. } = event {
+                    if depth == matched_depth {
+                        self.matched = None;
+                    }
+                }
+                return Ok(Some((pattern, event)));
+            }
+        }
+    }
+
+    /// Calls the given function for every event of matching subtrees until the end
+    /// of the document.
+    pub fn for_each_match<F>(&mut self, mut f: F) -> Result<(), Error> where F: FnMut(usize, XmlEvent) {
+        while let Some((pattern, event)) = try!(self.next()) {
+            f(pattern, event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reader::EventReader;
+    use reader::events::XmlEvent;
+    use super::{PathMatcher, PathPattern};
+
+    fn matched_text(data: &'static str, patterns: &[&str], bindings: &[(&str, &str)]) -> Vec<(usize, String)> {
+        let mut matcher = PathMatcher::new(EventReader::new_from_str_slice(data));
+        for pattern in patterns.iter() {
+            matcher.add_pattern(*pattern).unwrap();
+        }
+        for &(prefix, uri) in bindings.iter() {
+            matcher.namespace(prefix, uri);
+        }
+
+        let mut result: Vec<(usize, String)> = Vec::new();
+        let mut depth = 0;
+        while let Some((pattern, event)) = matcher.next().unwrap() {
+            match event {
+                XmlEvent::StartElement { .. } => {
+                    if depth == 0 {
+                        result.push((pattern, String::new()));
+                    }
+                    depth += 1;
+                }
+                XmlEvent::EndElement { .. } => depth -= 1,
+                XmlEvent::Characters(data) => result.last_mut().unwrap().1.push_str(data.as_slice()),
+                _ => {}
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn matches_absolute_and_descendant_paths() {
+        let data = "<feed><title>Feed</title><entry><title>One</title></entry>\
+                    <entry><title>Two</title><x><title>Nested</title></x></entry></feed>";
+        assert_eq!(matched_text(data, &["/feed/entry/title"], &[]),
+                   vec![(0, "One".to_string()), (0, "Two".to_string())]);
+        assert_eq!(matched_text(data, &["//x/title", "/feed/title"], &[]),
+                   vec![(1, "Feed".to_string()), (0, "Nested".to_string())]);
+        assert_eq!(matched_text(data, &["/feed//x"], &[]), vec![(0, "Nested".to_string())]);
+    }
+
+    #[test]
+    fn matches_attribute_predicates() {
+        let data = r#"<items><item type="x">1</item><item type="y">2</item><g><item type='x' n="a">3</item></g></items>"#;
+        assert_eq!(matched_text(data, &["//item[@type='x']"], &[]),
+                   vec![(0, "1".to_string()), (0, "3".to_string())]);
+        assert_eq!(matched_text(data, &["*[ @n ]", "g/*[@type = \"x\"]"], &[]),
+                   vec![(0, "3".to_string())]);
+    }
+
+    #[test]
+    fn matches_namespaces() {
+        let data = r#"<o:orders xmlns:o="urn:orders" xmlns="urn:default">
+                        <o:order><o:line>1</o:line><line>2</line></o:order>
+                      </o:orders>"#;
+        // prefixes from the document
+        assert_eq!(matched_text(data, &["o:order/o:line"], &[]), vec![(0, "1".to_string())]);
+        // explicitly bound prefixes
+        assert_eq!(matched_text(data, &["ns:order/ns:line"], &[("ns", "urn:orders")]),
+                   vec![(0, "1".to_string())]);
+        assert_eq!(matched_text(data, &["ns:order/*"], &[("ns", "urn:orders")]).len(), 2);
+        // unprefixed names do not match elements in the default namespace
+        assert!(matched_text(data, &["/o:orders/o:order/line"], &[]).is_empty());
+        assert_eq!(matched_text(data, &["/o:orders/o:order/d:line"], &[("d", "urn:default")]),
+                   vec![(0, "2".to_string())]);
+        assert_eq!(matched_text("<a><b xmlns=\"urn:x\">1</b><b>2</b></a>", &["//b"], &[]),
+                   vec![(0, "2".to_string())]);
+        assert!(matched_text(data, &["//q:line"], &[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(PathPattern::parse("").is_err());
+        assert!(PathPattern::parse("a/").is_err());
+        assert!(PathPattern::parse("a[@b='c'").is_err());
+        assert!(PathPattern::parse("a[b]").is_err());
+        assert!(PathPattern::parse("//a:*[@x:y]").is_ok());
+    }
+}
@@ -6,8 +6,16 @@
 use std::io::Buffer;
 use std::io::{MemReader, BufReader};
 
+use rustc_serialize::Decodable;
+
+use common::HasPosition;
+use namespace::NamespaceStack;
+use mapping::Mapping;
+use de::Records;
+
 use self::parser::PullParser;
 use self::events::XmlEvent;
+use self::context::ReaderContext;
 
 pub use self::config::ParserConfig;
 
@@ -15,6 +23,10 @@ mod lexer;
 mod parser;
 pub mod config;
 pub mod events;
+pub mod context;
+pub mod cursor;
+pub mod sax;
+pub mod matcher;
 
 /// Simple wrapper around an `std::io::Buffer` which provides pull-based XML parsing.
 pub struct EventReader<B> {
@@ -44,6 +56,45 @@ impl<B: Buffer> EventReader<B> {
         self.parser.next(&mut self.source)
     }
 
+    /// Returns the namespace mappings which are in scope at the last event.
+    ///
+    /// `StartElement` events only contain namespace declarations made on the element
+    /// itself. Use this method to obtain the full namespace context; `squash()` combines
+    /// it into a single `Namespace` if needed. For an `EndElement` event the context still
+    /// includes the declarations of the closed element.
+    #[inline]
+    pub fn namespace_stack(&self) -> &NamespaceStack {
+        self.parser.namespace_stack()
+    }
+
+    /// Returns the parser context at the last event.
+    ///
+    /// The context contains the path of open elements, namespace mappings in scope
+    /// and inherited values of `xml:lang`, `xml:space` and `xml:base` attributes. An element
+    /// is considered open at both its `StartElement` and `EndElement` events.
+    #[inline]
+    pub fn context(&self) -> ReaderContext {
+        ReaderContext::new(self.parser.element_stack(), self.parser.namespace_stack())
+    }
+
+    /// Returns an iterator which decodes every element with the given namespace URI and
+    /// local name into a value of type `T`, one element at a time.
+    ///
+    /// See `de::Records` for details.
+    #[inline]
+    pub fn records<'a, T: Decodable>(&'a mut self, namespace: Option<&str>,
+                                     local_name: &str) -> Records<'a, B, T> {
+        Records::new(self, namespace, local_name, Mapping::new())
+    }
+
+    /// Same as `records()`, but uses the given field mapping.
+    #[inline]
+    pub fn records_with_mapping<'a, T: Decodable>(&'a mut self, namespace: Option<&str>,
+                                                  local_name: &str,
+                                                  mapping: Mapping) -> Records<'a, B, T> {
+        Records::new(self, namespace, local_name, mapping)
+    }
+
     /// Returns an iterator over XML events.
     ///
     /// When the next event is `xml::event::Error` or `xml::event::EndDocument`, then
@@ -54,6 +105,16 @@ impl<B: Buffer> EventReader<B> {
     }
 }
 
+impl<B: Buffer> HasPosition for EventReader<B> {
+    /// Returns the row of the last event read from the stream.
+    #[inline]
+    fn row(&self) -> usize { self.parser.row() }
+
+    /// Returns the column of the last event read from the stream.
+    #[inline]
+    fn col(&self) -> usize { self.parser.col() }
+}
+
 /// XML events iterator, created by `events()` method on `Parser`.
 pub struct Events<'a, B: 'a> {
     reader: &'a mut EventReader<B>,
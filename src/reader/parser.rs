@@ -3,14 +3,15 @@
 use std::mem;
 
 use common;
-use common::{Error, XmlVersion, is_name_start_char, is_name_char, is_whitespace_char};
+use common::{Error, HasPosition, XmlVersion, is_name_start_char, is_name_char, is_whitespace_char};
 use name::OwnedName;
 use attribute::OwnedAttribute;
 use namespace;
 use namespace::{NamespaceStack};
 
 use reader::events::XmlEvent;
-use reader::config::ParserConfig;
+use reader::config::{ParserConfig, WhitespacePolicy};
+use reader::context::{OpenElement, XmlSpace};
 use reader::lexer;
 use reader::lexer::{PullLexer, Token};
 
@@ -18,7 +19,7 @@ static DEFAULT_VERSION: XmlVersion      = XmlVersion::Version10;
 static DEFAULT_ENCODING: &'static str   = "UTF-8";
 static DEFAULT_STANDALONE: Option<bool> = None;
 
-type ElementStack = Vec<OwnedName>;
+type ElementStack = Vec<OpenElement>;
 
 /// Pull-based XML parser.
 pub struct PullParser {
@@ -32,12 +33,13 @@ pub struct PullParser {
     finish_event: Option<XmlEvent>,
     next_event: Option<XmlEvent>,
     est: ElementStack,
+    wst: Vec<bool>,  // whether whitespace is trimmed inside each open element
 
     encountered_element: bool,
     parsed_declaration: bool,
     inside_whitespace: bool,
     read_prefix_separator: bool,
-    pop_namespace: bool
+    pop_element: bool
 }
 
 impl PullParser {
@@ -64,16 +66,43 @@ impl PullParser {
             finish_event: None,
             next_event: None,
             est: Vec::new(),
+            wst: Vec::new(),
 
             encountered_element: false,
             parsed_declaration: false,
             inside_whitespace: true,
             read_prefix_separator: false,
-            pop_namespace: false
+            pop_element: false
         }
     }
 }
 
+impl PullParser {
+    /// Returns the stack of namespaces which are in scope at the current event.
+    #[inline]
+    pub fn namespace_stack(&self) -> &NamespaceStack {
+        &self.nst
+    }
+
+    /// Returns the stack of elements which are open at the current event.
+    ///
+    /// The element of the last `StartElement` or `EndElement` event is still on the stack.
+    #[inline]
+    pub fn element_stack(&self) -> &[OpenElement] {
+        self.est.as_slice()
+    }
+}
+
+impl HasPosition for PullParser {
+    /// Returns the row of the last token read by the parser.
+    #[inline]
+    fn row(&self) -> usize { self.lexer.row() }
+
+    /// Returns the column of the last token read by the parser.
+    #[inline]
+    fn col(&self) -> usize { self.lexer.col() }
+}
+
 #[derive(Clone, PartialEq)]
 enum State {
     OutsideTag,
@@ -237,9 +266,13 @@ impl PullParser {
             return mem::replace(&mut self.next_event, None).unwrap();
         }
 
-        if self.pop_namespace {
-            self.pop_namespace = false;
+        // the element which was closed by the previous event is popped only now
+        // in order to keep it available in the context of that event
+        if self.pop_element {
+            self.pop_element = false;
             self.nst.pop();
+            self.est.pop();
+            self.wst.pop();
         }
 
         for_each!(t in self.lexer.next_token(r) ; {
@@ -305,6 +338,13 @@ impl PullParser {
         self.est.len()
     }
 
+    /// Returns whether whitespace should be trimmed in the current element, taking
+    /// `xml:space` attributes and configured whitespace policies into account.
+    #[inline]
+    fn trim_whitespace(&self) -> bool {
+        self.wst.last().map(|&t| t).unwrap_or(self.config.trim_whitespace)
+    }
+
     #[inline]
     fn buf_has_data(&self) -> bool {
         self.buf.len() > 0
@@ -465,11 +505,12 @@ impl PullParser {
                 // or a whitespace
                 let mut next_event = if self.buf_has_data() {
                     let buf = self.take_buf();
-                    if self.inside_whitespace && self.config.trim_whitespace {
+                    let trim_whitespace = self.trim_whitespace();
+                    if self.inside_whitespace && trim_whitespace {
                         None
                     } else if self.inside_whitespace && !self.config.whitespace_to_characters {
                         Some(XmlEvent::Whitespace(buf))
-                    } else if self.config.trim_whitespace {
+                    } else if trim_whitespace {
                         Some(XmlEvent::Characters(buf.as_slice().trim_matches(is_whitespace_char).to_string()))
                     } else {
                         Some(XmlEvent::Characters(buf))
@@ -780,15 +821,44 @@ impl PullParser {
             }
         }
 
+        let mut element = OpenElement::new(name.clone());
+        for attr in attributes.iter() {
+            if attr.name.namespace_as_ref() == Some(namespace::NS_XML_URI) {
+                match attr.name.local_name.as_slice() {
+                    "lang"  => element.lang = Some(attr.value.clone()),
+                    "base"  => element.base = Some(attr.value.clone()),
+                    "space" => element.space = match attr.value.as_slice() {
+                        "default"  => Some(XmlSpace::Default),
+                        "preserve" => Some(XmlSpace::Preserve),
+                        _          => None
+                    },
+                    _ => {}
+                }
+            }
+        }
+        let trim_whitespace = match element.space {
+            Some(XmlSpace::Preserve) => false,
+            Some(XmlSpace::Default) => self.config.trim_whitespace,
+            None => match self.config.whitespace_policies.get(
+                &(name.namespace.clone(), name.local_name.clone())
+            ) {
+                Some(&WhitespacePolicy::Preserve) => false,
+                Some(&WhitespacePolicy::Trim) => true,
+                None => self.trim_whitespace()
+            }
+        };
+        self.wst.push(trim_whitespace);
+        self.est.push(element);
+
         if emit_end_element {
-            self.pop_namespace = true;
+            self.pop_element = true;
             self.next_event = Some(XmlEvent::EndElement {
                 name: name.clone()
             });
-        } else {
-            self.est.push(name.clone());
         }
-        let namespace = self.nst.squash();
+        // only the declarations made on this element are reported; the full mapping
+        // is available through namespace_stack()
+        let namespace = self.nst.peek().clone();
         self.into_state_emit(State::OutsideTag, XmlEvent::StartElement {
             name: name,
             attributes: attributes.into_iter().map(|a| a.into_attribute()).collect(),
@@ -897,10 +967,10 @@ impl PullParser {
             None => return Some(self_error!(self; "Element {} prefix is unbound", name.to_string()))
         }
 
-        let op_name = self.est.pop().unwrap();
+        let op_name = self.est.last().unwrap().name.clone();
 
         if name == op_name {
-            self.pop_namespace = true;
+            self.pop_element = true;
             self.into_state_emit(State::OutsideTag, XmlEvent::EndElement { name: name })
         } else {
             Some(self_error!(self; "Unexpected closing tag: {}, expected {}", name.to_string(), op_name.to_string()))
@@ -1092,6 +1162,58 @@ mod tests {
         expect_event!(r, p, XmlEvent::EndDocument);
     }
 
+    #[test]
+    fn start_element_contains_only_declared_namespaces() {
+        let (mut r, mut p) = test_data!(r#"
+            <a xmlns:x="urn:x"><b xmlns:y="urn:y"/></a>
+        "#);
+
+        expect_event!(r, p, XmlEvent::StartDocument { .. });
+        expect_event!(r, p, XmlEvent::StartElement { ref namespace, .. }
+            [ namespace.0.len() == 1 && namespace.get(&Some("x".to_string())) == Some("urn:x") ]
+        );
+        expect_event!(r, p, XmlEvent::StartElement { ref namespace, .. }
+            [ namespace.0.len() == 1 && namespace.get(&Some("y".to_string())) == Some("urn:y") ]
+        );
+        assert_eq!(p.namespace_stack().get(&Some("x".to_string())), Some("urn:x"));
+        assert_eq!(p.namespace_stack().get(&Some("y".to_string())), Some("urn:y"));
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        assert_eq!(p.namespace_stack().get(&Some("y".to_string())), None);
+        expect_event!(r, p, XmlEvent::EndDocument);
+    }
+
+    #[test]
+    fn trim_whitespace_respects_xml_space_and_policies() {
+        use reader::config::WhitespacePolicy;
+
+        static DATA: &'static str = "<a> x <b xml:space=\"preserve\"> y <c xml:space=\"default\"> z </c></b><pre> w <i> v </i></pre></a>";
+        let mut r = BufReader::new(DATA.as_bytes());
+        let mut p = PullParser::new(
+            ParserConfig::new()
+                .trim_whitespace(true)
+                .whitespace_policy(None, "pre", WhitespacePolicy::Preserve)
+        );
+
+        expect_event!(r, p, XmlEvent::StartDocument { .. });
+        expect_event!(r, p, XmlEvent::StartElement { .. });
+        expect_event!(r, p, XmlEvent::Characters(ref s) [ s.as_slice() == "x" ]);
+        expect_event!(r, p, XmlEvent::StartElement { .. });
+        expect_event!(r, p, XmlEvent::Characters(ref s) [ s.as_slice() == " y " ]);
+        expect_event!(r, p, XmlEvent::StartElement { .. });
+        expect_event!(r, p, XmlEvent::Characters(ref s) [ s.as_slice() == "z" ]);
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::StartElement { .. });
+        expect_event!(r, p, XmlEvent::Characters(ref s) [ s.as_slice() == " w " ]);
+        expect_event!(r, p, XmlEvent::StartElement { .. });
+        expect_event!(r, p, XmlEvent::Characters(ref s) [ s.as_slice() == " v " ]);
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::EndElement { .. });
+        expect_event!(r, p, XmlEvent::EndDocument);
+    }
+
     #[test]
     fn opening_tag_in_attribute_value() {
         let (mut r, mut p) = test_data!(r#"
@@ -0,0 +1,346 @@
+//! Contains a SAX-like callback-based interface on top of the pull parser.
+//!
+//! Handlers are split into three traits, similarly to SAX2: `ContentHandler` receives
+//! the logical structure of the document, `LexicalHandler` receives lexical details like
+//! comments and CDATA section boundaries, and `DtdHandler` receives DTD declarations.
+//! All handler methods have empty default implementations, so it is only necessary to
+//! implement those which are interesting.
+//!
+//! `SaxDriver` pulls events from an `EventReader` and dispatches them to the handlers.
+//! Any handler method can abort parsing by returning an error message; it is converted to
+//! a `common::Error` with the position of the reader at the moment of failure.
+
+use std::io::Buffer;
+
+use common::Error;
+use name::OwnedName;
+use attribute::OwnedAttribute;
+use namespace::{NamespaceIterable, NS_XML_PREFIX, NS_XMLNS_PREFIX};
+
+use reader::EventReader;
+use reader::events::XmlEvent;
+
+/// Result of a handler method invocation.
+///
+/// `Err` contains a message which describes why the parsing should be aborted.
+pub type HandlerResult = Result<(), String>;
+
+/// Receives notifications about the logical content of a document.
+pub trait ContentHandler {
+    /// Called once at the beginning of a document.
+    fn start_document(&mut self) -> HandlerResult { Ok(()) }
+
+    /// Called once at the end of a document, after all other methods.
+    fn end_document(&mut self) -> HandlerResult { Ok(()) }
+
+    /// Called before `start_element()` for each namespace prefix declared on the element.
+    ///
+    /// `None` prefix denotes the default namespace.
+    fn start_prefix_mapping(&mut self, _prefix: Option<&str>, _uri: &str) -> HandlerResult { Ok(()) }
+
+    /// Called after `end_element()` for each prefix declared on the element.
+    fn end_prefix_mapping(&mut self, _prefix: Option<&str>) -> HandlerResult { Ok(()) }
+
+    /// Called at the beginning of every element.
+    ///
+    /// Namespace declarations are not included into the attributes; they are reported
+    /// through `start_prefix_mapping()` instead.
+    fn start_element(&mut self, _name: &OwnedName, _attributes: &[OwnedAttribute]) -> HandlerResult { Ok(()) }
+
+    /// Called at the end of every element, including empty ones.
+    fn end_element(&mut self, _name: &OwnedName) -> HandlerResult { Ok(()) }
+
+    /// Called for character data, including the contents of CDATA sections.
+    fn characters(&mut self, _data: &str) -> HandlerResult { Ok(()) }
+
+    /// Called for whitespace-only character data.
+    ///
+    /// The parser is not validating, so this is whitespace the parser reports as
+    /// `Whitespace` events; see `ParserConfig` for options which affect it.
+    fn ignorable_whitespace(&mut self, _data: &str) -> HandlerResult { Ok(()) }
+
+    /// Called for every processing instruction except the XML declaration.
+    fn processing_instruction(&mut self, _target: &str, _data: Option<&str>) -> HandlerResult { Ok(()) }
+}
+
+/// Receives notifications about lexical details of a document.
+pub trait LexicalHandler {
+    /// Called at the beginning of the document type declaration.
+    fn start_dtd(&mut self, _name: &str, _public_id: Option<&str>, _system_id: Option<&str>) -> HandlerResult { Ok(()) }
+
+    /// Called at the end of the document type declaration.
+    fn end_dtd(&mut self) -> HandlerResult { Ok(()) }
+
+    /// Called at the beginning of a general entity expansion.
+    fn start_entity(&mut self, _name: &str) -> HandlerResult { Ok(()) }
+
+    /// Called at the end of a general entity expansion.
+    fn end_entity(&mut self, _name: &str) -> HandlerResult { Ok(()) }
+
+    /// Called at the beginning of a CDATA section, before its contents are reported
+    /// through `ContentHandler::characters()`.
+    fn start_cdata(&mut self) -> HandlerResult { Ok(()) }
+
+    /// Called at the end of a CDATA section.
+    fn end_cdata(&mut self) -> HandlerResult { Ok(()) }
+
+    /// Called for every comment.
+    ///
+    /// Comments are ignored by the parser by default; set `ParserConfig::ignore_comments`
+    /// to false to receive them.
+    fn comment(&mut self, _data: &str) -> HandlerResult { Ok(()) }
+}
+
+/// Receives notifications about notation and unparsed entity declarations.
+pub trait DtdHandler {
+    /// Called for every notation declaration.
+    fn notation_decl(&mut self, _name: &str, _public_id: Option<&str>, _system_id: Option<&str>) -> HandlerResult { Ok(()) }
+
+    /// Called for every unparsed entity declaration.
+    fn unparsed_entity_decl(&mut self, _name: &str, _public_id: Option<&str>,
+                            _system_id: &str, _notation_name: &str) -> HandlerResult { Ok(()) }
+}
+
+/// Drives an `EventReader` and dispatches its events to SAX handlers.
+///
+/// The parser does not process document type declarations yet (see the crate
+/// documentation), so `LexicalHandler::start_dtd()`/`end_dtd()`, entity boundaries
+/// and `DtdHandler` methods are currently never invoked. Only predefined and
+/// character entities are expanded, and SAX does not report those.
+///
+/// ```rust,ignore
+/// let mut handler = MyHandler::new();
+/// let mut reader = EventReader::new_from_str_slice(source);
+/// SaxDriver::new()
+///     .content_handler(&mut handler)
+///     .parse(&mut reader)
+/// ```
+pub struct SaxDriver<'a> {
+    content_handler: Option<&'a mut (ContentHandler + 'a)>,
+    lexical_handler: Option<&'a mut (LexicalHandler + 'a)>,
+    dtd_handler: Option<&'a mut (DtdHandler + 'a)>
+}
+
+macro_rules! dispatch(
+    ($this:ident, $reader:ident; $handler:ident . $method:ident ( $($arg:expr),* )) => (
+        if let Some(ref mut handler) = $this.$handler {
+            match handler.$method($($arg),*) {
+                Ok(()) => {}
+                Err(msg) => return Err(Error::new(&*$reader, msg))
+            }
+        }
+    )
+);
+
+impl<'a> SaxDriver<'a> {
+    /// Creates a driver without any handlers set.
+    #[inline]
+    pub fn new() -> SaxDriver<'a> {
+        SaxDriver {
+            content_handler: None,
+            lexical_handler: None,
+            dtd_handler: None
+        }
+    }
+
+    /// Sets the content handler and returns updated driver.
+    #[inline]
+    pub fn content_handler(mut self, handler: &'a mut (ContentHandler + 'a)) -> SaxDriver<'a> {
+        self.content_handler = Some(handler);
+        self
+    }
+
+    /// Sets the lexical handler and returns updated driver.
+    #[inline]
+    pub fn lexical_handler(mut self, handler: &'a mut (LexicalHandler + 'a)) -> SaxDriver<'a> {
+        self.lexical_handler = Some(handler);
+        self
+    }
+
+    /// Sets the DTD handler and returns updated driver.
+    #[inline]
+    pub fn dtd_handler(mut self, handler: &'a mut (DtdHandler + 'a)) -> SaxDriver<'a> {
+        self.dtd_handler = Some(handler);
+        self
+    }
+
+    /// Parses the whole document from the given reader, invoking the handlers.
+    ///
+    /// Parsing stops at the first parser error or at the first handler error; both
+    /// are returned as `Err`.
+    pub fn parse<B: Buffer>(&mut self, reader: &mut EventReader<B>) -> Result<(), Error> {
+        // prefixes declared on every open element
+        let mut declared: Vec<Vec<Option<String>>> = Vec::new();
+
+        loop {
+            match reader.next() {
+                XmlEvent::StartDocument { .. } =>
+                    dispatch!(self, reader; content_handler.start_document()),
+
+                XmlEvent::EndDocument => {
+                    dispatch!(self, reader; content_handler.end_document());
+                    return Ok(());
+                }
+
+                XmlEvent::ProcessingInstruction { ref name, ref data } =>
+                    dispatch!(self, reader; content_handler.processing_instruction(
+                        name.as_slice(), data.as_ref().map(|d| d.as_slice())
+                    )),
+
+                XmlEvent::StartElement { ref name, ref attributes, ref namespace } => {
+                    let mut prefixes = Vec::new();
+                    for (prefix, uri) in namespace.uri_mappings() {
+                        match prefix {
+                            Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) => continue,
+                            _ => {}
+                        }
+                        dispatch!(self, reader; content_handler.start_prefix_mapping(prefix, uri));
+                        prefixes.push(prefix.map(|p| p.to_string()));
+                    }
+                    declared.push(prefixes);
+
+                    dispatch!(self, reader; content_handler.start_element(name, attributes.as_slice()));
+                }
+
+                XmlEvent::EndElement { ref name } => {
+                    dispatch!(self, reader; content_handler.end_element(name));
+
+                    for prefix in declared.pop().unwrap_or(Vec::new()).iter().rev() {
+                        dispatch!(self, reader; content_handler.end_prefix_mapping(
+                            prefix.as_ref().map(|p| p.as_slice())
+                        ));
+                    }
+                }
+
+                XmlEvent::CData(ref data) => {
+                    dispatch!(self, reader; lexical_handler.start_cdata());
+                    dispatch!(self, reader; content_handler.characters(data.as_slice()));
+                    dispatch!(self, reader; lexical_handler.end_cdata());
+                }
+
+                XmlEvent::Comment(ref data) =>
+                    dispatch!(self, reader; lexical_handler.comment(data.as_slice())),
+
+                XmlEvent::Characters(ref data) =>
+                    dispatch!(self, reader; content_handler.characters(data.as_slice())),
+
+                XmlEvent::Whitespace(ref data) =>
+                    dispatch!(self, reader; content_handler.ignorable_whitespace(data.as_slice())),
+
+                XmlEvent::Error(e) => return Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use name::OwnedName;
+    use attribute::OwnedAttribute;
+    use common::HasPosition;
+
+    use reader::EventReader;
+    use reader::ParserConfig;
+    use super::{SaxDriver, ContentHandler, LexicalHandler, HandlerResult};
+
+    struct Recorder {
+        log: Vec<String>
+    }
+
+    impl ContentHandler for Recorder {
+        fn start_prefix_mapping(&mut self, prefix: Option<&str>, uri: &str) -> HandlerResult {
+            self.log.push(format!("start-prefix {:?} {}", prefix, uri));
+            Ok(())
+        }
+
+        fn end_prefix_mapping(&mut self, prefix: Option<&str>) -> HandlerResult {
+            self.log.push(format!("end-prefix {:?}", prefix));
+            Ok(())
+        }
+
+        fn start_element(&mut self, name: &OwnedName, attributes: &[OwnedAttribute]) -> HandlerResult {
+            if name.local_name.as_slice() == "stop" {
+                return Err("stopped".to_string());
+            }
+            self.log.push(format!("start {} {}", name.local_name, attributes.len()));
+            Ok(())
+        }
+
+        fn end_element(&mut self, name: &OwnedName) -> HandlerResult {
+            self.log.push(format!("end {}", name.local_name));
+            Ok(())
+        }
+
+        fn characters(&mut self, data: &str) -> HandlerResult {
+            self.log.push(format!("chars {}", data));
+            Ok(())
+        }
+    }
+
+    impl LexicalHandler for Recorder {
+        fn start_cdata(&mut self) -> HandlerResult {
+            self.log.push("start-cdata".to_string());
+            Ok(())
+        }
+
+        fn end_cdata(&mut self) -> HandlerResult {
+            self.log.push("end-cdata".to_string());
+            Ok(())
+        }
+
+        fn comment(&mut self, data: &str) -> HandlerResult {
+            self.log.push(format!("comment {}", data));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dispatches_events_to_handlers() {
+        let mut content = Recorder { log: Vec::new() };
+        let mut lexical = Recorder { log: Vec::new() };
+        let mut reader = EventReader::new_with_config(
+            ::std::io::BufReader::new(
+                b"<p:a xmlns:p=\"urn:x\" k=\"v\"><!--c--><b>t<![CDATA[d]]></b></p:a>"
+            ),
+            ParserConfig::new().ignore_comments(false)
+        );
+
+        let result = SaxDriver::new()
+            .content_handler(&mut content)
+            .lexical_handler(&mut lexical)
+            .parse(&mut reader);
+        assert!(result.is_ok());
+
+        assert_eq!(content.log, vec![
+            "start-prefix Some(\"p\") urn:x".to_string(),
+            "start a 1".to_string(),
+            "start b 0".to_string(),
+            "chars t".to_string(),
+            "chars d".to_string(),
+            "end b".to_string(),
+            "end a".to_string(),
+            "end-prefix Some(\"p\")".to_string()
+        ]);
+        assert_eq!(lexical.log, vec![
+            "comment c".to_string(),
+            "start-cdata".to_string(),
+            "end-cdata".to_string()
+        ]);
+    }
+
+    #[test]
+    fn handler_error_aborts_parsing() {
+        let mut content = Recorder { log: Vec::new() };
+        let mut reader = EventReader::new_from_str_slice("<a>\n  <stop/><b/></a>");
+
+        let result = SaxDriver::new().content_handler(&mut content).parse(&mut reader);
+        match result {
+            Err(ref e) => {
+                assert_eq!(e.msg(), "stopped");
+                assert_eq!(e.row(), 1);
+            }
+            Ok(_) => panic!("Parsing should have been aborted")
+        }
+        assert_eq!(content.log, vec!["start a 0".to_string()]);
+    }
+}
@@ -0,0 +1,586 @@
+//! Contains an encoder which writes Rust values as XML documents.
+//!
+//! This is the counterpart of `de` module: values are encoded with `rustc-serialize`, so
+//! any type which implements `Encodable`, including types with `#[derive(RustcEncodable)]`,
+//! can be written to an `EventWriter`, and documents written by the encoder can be decoded
+//! back into the same types:
+//!
+//! * a struct is written as an element; every field is written as child elements named
+//!   after the field;
+//! * a sequence is written as repeated elements with the same name;
+//! * `None` is not written at all;
+//! * an enum variant with arguments is written as an element named after the variant,
+//!   wrapped into the element for the field; a unit variant is written as its name;
+//! * primitive values and strings are written as text.
+//!
+//! A `Mapping` can turn fields into attributes or into the text content of an element and
+//! can give them namespace-qualified names. Prefixes of qualified names are declared on the
+//! elements which use them unless they are already in scope, so every namespace-qualified
+//! name in a mapping must have a prefix.
+//!
+//! The root element is named after the encoded struct or enum, unless a root name is given.
+//!
+//! Events are written as the value is encoded, without building the document in memory.
+//! Attributes are added to the start tag of their element, so fields mapped to attributes
+//! must precede the fields written as its content.
+
+use std::fmt;
+use std::error;
+use std::mem;
+use std::io::ByRefWriter;
+
+use rustc_serialize::{self, Encodable};
+
+use name::OwnedName;
+use namespace::NamespaceStack;
+use mapping::{Mapping, FieldMapping};
+
+use writer::{EventWriter, EmitterConfig, EmitterError};
+
+/// Encoding error.
+#[derive(Clone, PartialEq)]
+pub enum EncoderError {
+    /// The value cannot be represented in XML with the given mapping.
+    Mapping(String),
+
+    /// The underlying writer has failed.
+    Writer(EmitterError)
+}
+
+impl fmt::Show for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncoderError::Mapping(ref msg) => write!(f, "Mapping error: {}", msg),
+            EncoderError::Writer(ref e) => write!(f, "{:?}", e)
+        }
+    }
+}
+
+impl error::Error for EncoderError {
+    fn description(&self) -> &str {
+        match *self {
+            EncoderError::Mapping(ref msg) => msg.as_slice(),
+            EncoderError::Writer(_) => "Writer error"
+        }
+    }
+
+    fn detail(&self) -> Option<String> { Some(format!("{:?}", self)) }
+}
+
+/// Result of encoding.
+pub type EncodeResult<T> = Result<T, EncoderError>;
+
+/// Writes the given value as an element to the given writer.
+#[inline]
+pub fn to_writer<W: Writer, T: Encodable>(writer: &mut EventWriter<W>, value: &T) -> EncodeResult<()> {
+    to_writer_with_mapping(writer, value, None, &Mapping::new())
+}
+
+/// Writes the given value as an element to the given writer, using the given field
+/// mapping and root element name.
+pub fn to_writer_with_mapping<W: Writer, T: Encodable>(writer: &mut EventWriter<W>, value: &T,
+                                                      root: Option<OwnedName>,
+                                                      mapping: &Mapping) -> EncodeResult<()> {
+    let mut encoder = Encoder::new(writer, mapping, root);
+    try!(value.encode(&mut encoder));
+    encoder.finish()
+}
+
+/// Writes the given value as a document into a string.
+#[inline]
+pub fn to_string<T: Encodable>(value: &T) -> EncodeResult<String> {
+    to_string_with_config(value, None, &Mapping::new(), EmitterConfig::new())
+}
+
+/// Writes the given value as a document into a string, using the given field mapping,
+/// root element name and emitter configuration.
+pub fn to_string_with_config<T: Encodable>(value: &T, root: Option<OwnedName>, mapping: &Mapping,
+                                           config: EmitterConfig) -> EncodeResult<String> {
+    let mut output = Vec::new();
+    {
+        let mut writer = EventWriter::new_with_config(output.by_ref(), config);
+        try!(to_writer_with_mapping(&mut writer, value, root, mapping));
+    }
+    // the emitter only writes valid UTF-8
+    Ok(String::from_utf8(output).unwrap())
+}
+
+/// Where an encoded value is written to.
+#[derive(Clone)]
+enum Target {
+    /// The root element, named after the encoded type.
+    Root,
+
+    /// Child elements with the given name.
+    Element(OwnedName),
+
+    /// An attribute of the current element.
+    Attribute,
+
+    /// Text content of the current element.
+    Text,
+
+    /// A key of a map entry.
+    Key
+}
+
+/// An encoder of values into events written to an `EventWriter`.
+pub struct Encoder<'a, W: 'a> {
+    writer: &'a mut EventWriter<W>,
+    mapping: &'a Mapping,
+    targets: Vec<Target>,
+    // namespaces declared on the open elements
+    namespaces: NamespaceStack,
+    // names of the structs being encoded
+    structs: Vec<String>,
+    // the last encoded map key
+    key: Option<String>,
+    // value of the attribute being encoded
+    attribute: Option<String>,
+    // whether an element has been written
+    written: bool
+}
+
+impl<'a, W: Writer> Encoder<'a, W> {
+    /// Creates an encoder which writes to the given writer using the given field mapping.
+    ///
+    /// If `root` is `None`, the root element is named after the encoded type.
+    pub fn new(writer: &'a mut EventWriter<W>, mapping: &'a Mapping,
+               root: Option<OwnedName>) -> Encoder<'a, W> {
+        Encoder {
+            writer: writer,
+            mapping: mapping,
+            targets: vec![match root {
+                Some(name) => Target::Element(name),
+                None => Target::Root
+            }],
+            namespaces: NamespaceStack::default(),
+            structs: Vec::new(),
+            key: None,
+            attribute: None,
+            written: false
+        }
+    }
+
+    /// Checks that the encoded value has been written, consuming the encoder.
+    pub fn finish(self) -> EncodeResult<()> {
+        if self.written {
+            Ok(())
+        } else {
+            Err(EncoderError::Mapping("Nothing has been encoded".to_string()))
+        }
+    }
+
+    fn is_root(&self) -> bool {
+        match *self.targets.last().unwrap() {
+            Target::Root => true,
+            _ => false
+        }
+    }
+
+    fn with<F>(&mut self, target: Target, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        self.targets.push(target);
+        let result = f(self);
+        self.targets.pop();
+        result
+    }
+
+    /// Declares the namespace of the given name on the current element, unless
+    /// it is already in scope.
+    fn declare(&mut self, name: &OwnedName) -> EncodeResult<()> {
+        match (name.prefix_as_ref(), name.namespace_as_ref()) {
+            (Some(prefix), Some(uri)) => {
+                let key = Some(prefix.to_string());
+                if self.namespaces.get(&key) == Some(uri) {
+                    return Ok(());
+                }
+                if !self.namespaces.put(key, uri.to_string()) {
+                    return Err(EncoderError::Mapping(
+                        format!("Prefix {} is bound to different namespaces", prefix)
+                    ));
+                }
+                self.writer.write_namespace(Some(prefix), uri).map_err(EncoderError::Writer)
+            }
+            (None, Some(_)) => Err(EncoderError::Mapping(
+                format!("Name {} has a namespace but no prefix", name)
+            )),
+            _ => Ok(())
+        }
+    }
+
+    /// Returns the name of the element the current value is written to.
+    fn element_name(&self, type_name: &str) -> EncodeResult<OwnedName> {
+        match *self.targets.last().unwrap() {
+            Target::Root => Ok(OwnedName::local(type_name)),
+            Target::Element(ref name) => Ok(name.clone()),
+            _ => Err(EncoderError::Mapping(format!("{} cannot be written as text", type_name)))
+        }
+    }
+
+    fn start_element(&mut self, name: OwnedName) -> EncodeResult<()> {
+        try!(self.writer.write_start_element(name.borrow()).map_err(EncoderError::Writer));
+        self.written = true;
+        self.namespaces.push_empty();
+        self.declare(&name)
+    }
+
+    fn end_element(&mut self) -> EncodeResult<()> {
+        self.namespaces.pop();
+        self.writer.write_end_element().map_err(EncoderError::Writer)
+    }
+
+    fn emit_text(&mut self, value: String) -> EncodeResult<()> {
+        let name = match self.targets.last().unwrap().clone() {
+            Target::Root => return Err(EncoderError::Mapping(
+                "A root element name is required to write a primitive value".to_string()
+            )),
+
+            Target::Element(name) => name,
+
+            Target::Attribute => {
+                // sequences are written as whitespace-separated lists
+                self.attribute = Some(match mem::replace(&mut self.attribute, None) {
+                    Some(previous) => format!("{} {}", previous, value),
+                    None => value
+                });
+                return Ok(());
+            }
+
+            Target::Text =>
+                return self.writer.write_characters(value.as_slice()).map_err(EncoderError::Writer),
+
+            Target::Key => {
+                self.key = Some(value);
+                return Ok(());
+            }
+        };
+        try!(self.start_element(name));
+        try!(self.writer.write_characters(value.as_slice()).map_err(EncoderError::Writer));
+        self.end_element()
+    }
+
+    /// Writes the attribute collected while encoding a field; nothing is written
+    /// for `None` and empty sequences.
+    ///
+    /// Attributes are added to the start tag of the current element, so fields mapped
+    /// to attributes must precede the fields written as its content.
+    fn write_attribute(&mut self, name: OwnedName) -> EncodeResult<()> {
+        match mem::replace(&mut self.attribute, None) {
+            Some(value) => {
+                try!(self.declare(&name));
+                self.writer.write_attribute(name.borrow(), value.as_slice()).map_err(EncoderError::Writer)
+            }
+            None => Ok(())
+        }
+    }
+
+    fn struct_field<F>(&mut self, f_name: &str, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        let mapping: &'a Mapping = self.mapping;
+        match mapping.get(self.structs.last().map(|s| s.as_slice()), f_name) {
+            Some(&FieldMapping::Attribute(ref name)) => {
+                try!(self.with(Target::Attribute, f));
+                self.write_attribute(name.clone())
+            }
+            Some(&FieldMapping::Element(ref name)) => self.with(Target::Element(name.clone()), f),
+            Some(&FieldMapping::Text) => self.with(Target::Text, f),
+            None => self.with(Target::Element(OwnedName::local(f_name)), f)
+        }
+    }
+
+    fn variant<F>(&mut self, v_name: &str, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        if len == 0 {
+            return self.emit_text(v_name.to_string());
+        }
+        let inner = Target::Element(OwnedName::local(v_name));
+        if self.is_root() {
+            return self.with(inner, f);
+        }
+        // the variant element is wrapped into the element of the field
+        let name = try!(self.element_name(v_name));
+        try!(self.start_element(name));
+        try!(self.with(inner, f));
+        self.end_element()
+    }
+}
+
+impl<'a, W: Writer> rustc_serialize::Encoder for Encoder<'a, W> {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> { Ok(()) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> { self.emit_text(v.to_string()) }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self, v_name: &str, _v_id: usize, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        self.variant(v_name, len, f)
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _a_idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self, v_name: &str, _v_id: usize, len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        let wrapped = !self.is_root();
+        if wrapped {
+            let name = try!(self.element_name(v_name));
+            try!(self.start_element(name));
+        }
+        try!(self.start_element(OwnedName::local(v_name)));
+        self.structs.push(v_name.to_string());
+        let result = if len == 0 { Ok(()) } else { f(self) };
+        self.structs.pop();
+        try!(result);
+        try!(self.end_element());
+        if wrapped {
+            try!(self.end_element());
+        }
+        Ok(())
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self, f_name: &str, _f_idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        self.struct_field(f_name, f)
+    }
+
+    fn emit_struct<F>(&mut self, name: &str, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        let element_name = try!(self.element_name(name));
+        try!(self.start_element(element_name));
+        self.structs.push(name.to_string());
+        let result = f(self);
+        self.structs.pop();
+        try!(result);
+        self.end_element()
+    }
+
+    fn emit_struct_field<F>(&mut self, f_name: &str, _f_idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        self.struct_field(f_name, f)
+    }
+
+    fn emit_tuple<F>(&mut self, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_tuple_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _name: &str, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_tuple_struct_arg<F>(&mut self, _f_idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult<()> { Ok(()) }
+
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        f(self)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        // a map is written as child elements named after its keys
+        let name = try!(self.element_name("map"));
+        try!(self.start_element(name));
+        try!(f(self));
+        self.end_element()
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        self.with(Target::Key, f)
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()>
+        where F: FnOnce(&mut Encoder<'a, W>) -> EncodeResult<()>
+    {
+        match mem::replace(&mut self.key, None) {
+            Some(key) => self.with(Target::Element(OwnedName::local(key)), f),
+            None => Err(EncoderError::Mapping("A map key must be a primitive value".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ByRefWriter;
+
+    use name::{Name, OwnedName};
+    use mapping::Mapping;
+    use writer::{EventWriter, EmitterConfig};
+    use de;
+    use super::{EncoderError, to_string, to_string_with_config, to_writer};
+
+    #[derive(RustcEncodable, RustcDecodable, Show, PartialEq)]
+    struct Catalog {
+        title: String,
+        status: Status,
+        library: Vec<Library>,
+        entry: Vec<Entry>
+    }
+
+    #[derive(RustcEncodable, RustcDecodable, Show, PartialEq)]
+    struct Library {
+        group_id: String,
+        version: String,
+        tags: Vec<String>,
+        license: Option<String>
+    }
+
+    #[derive(RustcEncodable, RustcDecodable, Show, PartialEq)]
+    enum Status {
+        Active,
+        Retired
+    }
+
+    #[derive(RustcEncodable, RustcDecodable, Show, PartialEq)]
+    enum Entry {
+        Note(String),
+        Library(Library)
+    }
+
+    fn library(group_id: &str, license: Option<&str>) -> Library {
+        Library {
+            group_id: group_id.to_string(),
+            version: "0.1".to_string(),
+            tags: vec!["xml".to_string(), "parser".to_string()],
+            license: license.map(|l| l.to_string())
+        }
+    }
+
+    #[test]
+    fn round_trip_with_mapping() {
+        let mapping = Mapping::new()
+            .attribute("Library.group_id", OwnedName::qualified("groupId", "urn:lib", Some("l")))
+            .attribute("Library.version", OwnedName::local("version"))
+            .attribute("tags", OwnedName::local("tags"))
+            .text("Catalog.title");
+        let catalog = Catalog {
+            title: "Libraries & notes".to_string(),
+            status: Status::Retired,
+            library: vec![library("org.example", Some("MIT")), library("com.example", None)],
+            entry: vec![Entry::Note("a note".to_string()), Entry::Library(library("net.example", None))]
+        };
+
+        let root = OwnedName::qualified("catalog", "urn:cat", Some("c"));
+        let output = to_string_with_config(&catalog, Some(root), &mapping, EmitterConfig::new()).unwrap();
+        assert!(output.as_slice().contains("<c:catalog xmlns:"));
+        assert!(output.as_slice().contains("l:groupId=\"org.example\""));
+        assert!(output.as_slice().contains("tags=\"xml parser\""));
+        assert!(output.as_slice().contains("<entry><Note>a note</Note></entry>"));
+
+        let decoded: Catalog = de::from_str_with_mapping(output.as_slice(), &mapping).unwrap();
+        assert_eq!(decoded, catalog);
+    }
+
+    #[test]
+    fn root_is_named_after_type() {
+        let config = EmitterConfig::new()
+            .perform_indent(true)
+            .write_document_declaration(false);
+        let output = to_string_with_config(&library("org.example", None), None, &Mapping::new(), config).unwrap();
+        assert!(output.as_slice().starts_with("<Library>"));
+        assert!(output.as_slice().contains("\n  <version>0.1</version>"));
+        assert!(!output.as_slice().contains("license"));
+
+        match to_string(&42u32) {
+            Err(EncoderError::Mapping(ref msg)) => assert!(msg.contains("root element name")),
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+        let output = to_string_with_config(&42u32, Some(OwnedName::local("answer")), &Mapping::new(),
+                                           EmitterConfig::new().write_document_declaration(false)).unwrap();
+        assert_eq!(output.as_slice(), "<answer>42</answer>");
+    }
+
+    #[test]
+    fn writes_into_open_element() {
+        let mut output = Vec::new();
+        {
+            let config = EmitterConfig::new().write_document_declaration(false);
+            let mut writer = EventWriter::new_with_config(output.by_ref(), config);
+            writer.write_start_element(Name::local("libraries")).ok().unwrap();
+            to_writer(&mut writer, &library("a", None)).unwrap();
+            to_writer(&mut writer, &library("b", None)).unwrap();
+            writer.write_end_element().ok().unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap().as_slice(),
+                   "<libraries>\
+                    <Library><group_id>a</group_id><version>0.1</version><tags>xml</tags><tags>parser</tags></Library>\
+                    <Library><group_id>b</group_id><version>0.1</version><tags>xml</tags><tags>parser</tags></Library>\
+                    </libraries>");
+
+        // attributes cannot follow the content of their element
+        let mapping = Mapping::new().attribute("Library.license", OwnedName::local("license"));
+        match to_string_with_config(&library("c", Some("MIT")), None, &mapping, EmitterConfig::new()) {
+            Err(EncoderError::Writer(_)) => {}
+            ref other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+}
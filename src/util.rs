@@ -48,9 +48,119 @@ impl<'a, I, K, V> Iterator for ClonedPairwise<'a, I, K, V>
     }
 }
 
+/// Components of a URI reference, as defined by [RFC 3986][1].
+///
+/// [1]: http://tools.ietf.org/html/rfc3986#section-3
+struct UriParts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>
+}
+
+fn split_uri(s: &str) -> UriParts {
+    let (rest, fragment) = match s.find('#') {
+        Some(i) => (s.slice_to(i), Some(s.slice_from(i + 1))),
+        None => (s, None)
+    };
+    let (rest, query) = match rest.find('?') {
+        Some(i) => (rest.slice_to(i), Some(rest.slice_from(i + 1))),
+        None => (rest, None)
+    };
+    let (scheme, rest) = match rest.find(':') {
+        Some(i) if i > 0 && !rest.slice_to(i).contains_char('/') &&
+                   rest.char_at(0).is_alphabetic() =>
+            (Some(rest.slice_to(i)), rest.slice_from(i + 1)),
+        _ => (None, rest)
+    };
+    let (authority, path) = if rest.starts_with("//") {
+        let rest = rest.slice_from(2);
+        match rest.find('/') {
+            Some(i) => (Some(rest.slice_to(i)), rest.slice_from(i)),
+            None => (Some(rest), "")
+        }
+    } else {
+        (None, rest)
+    };
+    UriParts { scheme: scheme, authority: authority, path: path, query: query, fragment: fragment }
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with("/");
+    let segments: Vec<&str> = path.split('/').collect();
+    let last = segments.len() - 1;
+
+    let mut output: Vec<&str> = Vec::new();
+    for (i, segment) in segments.into_iter().enumerate() {
+        if i == 0 && absolute { continue; }
+        match segment {
+            "." => {}
+            ".." => { output.pop(); }
+            s => { output.push(s); continue; }
+        }
+        // a trailing dot segment denotes a directory
+        if i == last { output.push(""); }
+    }
+
+    let mut result = if absolute { "/".to_string() } else { String::new() };
+    result.push_str(output.connect("/").as_slice());
+    result
+}
+
+/// Resolves a URI reference against a base URI as described in [RFC 3986, section 5.2][1].
+///
+/// No validation is performed on either argument; they are only split into components.
+///
+/// [1]: http://tools.ietf.org/html/rfc3986#section-5.2
+pub fn resolve_uri(base: &str, reference: &str) -> String {
+    let b = split_uri(base);
+    let r = split_uri(reference);
+
+    let (scheme, authority, path, query) = if r.scheme.is_some() {
+        (r.scheme, r.authority, remove_dot_segments(r.path), r.query)
+    } else if r.authority.is_some() {
+        (b.scheme, r.authority, remove_dot_segments(r.path), r.query)
+    } else if r.path.is_empty() {
+        (b.scheme, b.authority, b.path.to_string(), r.query.or(b.query))
+    } else if r.path.starts_with("/") {
+        (b.scheme, b.authority, remove_dot_segments(r.path), r.query)
+    } else {
+        let merged = if b.authority.is_some() && b.path.is_empty() {
+            format!("/{}", r.path)
+        } else {
+            match b.path.rfind('/') {
+                Some(i) => format!("{}{}", b.path.slice_to(i + 1), r.path),
+                None => r.path.to_string()
+            }
+        };
+        (b.scheme, b.authority, remove_dot_segments(merged.as_slice()), r.query)
+    };
+
+    let mut result = String::new();
+    if let Some(scheme) = scheme {
+        result.push_str(scheme);
+        result.push(':');
+    }
+    if let Some(authority) = authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    result.push_str(path.as_slice());
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = r.fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{OptionBorrowExt, IntoOwned, IteratorClonedPairwiseExt};
+    use super::{OptionBorrowExt, IntoOwned, IteratorClonedPairwiseExt, resolve_uri};
 
     #[test]
     fn test_borrow_value() {
@@ -95,4 +205,27 @@ mod tests {
         let v2: HashMap<String, Vec<usize>> = v1.iter().cloned_pairwise().collect();
         assert_eq!(v1, v2);
     }
+
+    #[test]
+    fn test_resolve_uri() {
+        // examples from RFC 3986, section 5.4
+        let base = "http://a/b/c/d;p?q";
+        assert_eq!(resolve_uri(base, "g:h").as_slice(), "g:h");
+        assert_eq!(resolve_uri(base, "g").as_slice(), "http://a/b/c/g");
+        assert_eq!(resolve_uri(base, "./g").as_slice(), "http://a/b/c/g");
+        assert_eq!(resolve_uri(base, "g/").as_slice(), "http://a/b/c/g/");
+        assert_eq!(resolve_uri(base, "/g").as_slice(), "http://a/g");
+        assert_eq!(resolve_uri(base, "//g").as_slice(), "http://g");
+        assert_eq!(resolve_uri(base, "?y").as_slice(), "http://a/b/c/d;p?y");
+        assert_eq!(resolve_uri(base, "g?y").as_slice(), "http://a/b/c/g?y");
+        assert_eq!(resolve_uri(base, "#s").as_slice(), "http://a/b/c/d;p?q#s");
+        assert_eq!(resolve_uri(base, "").as_slice(), "http://a/b/c/d;p?q");
+        assert_eq!(resolve_uri(base, ".").as_slice(), "http://a/b/c/");
+        assert_eq!(resolve_uri(base, "..").as_slice(), "http://a/b/");
+        assert_eq!(resolve_uri(base, "../g").as_slice(), "http://a/b/g");
+        assert_eq!(resolve_uri(base, "../../g").as_slice(), "http://a/g");
+        assert_eq!(resolve_uri(base, "../../../g").as_slice(), "http://a/g");
+        assert_eq!(resolve_uri(base, "/./g").as_slice(), "http://a/g");
+        assert_eq!(resolve_uri(base, "g;x=1/../y").as_slice(), "http://a/b/c/y");
+    }
 }
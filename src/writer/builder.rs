@@ -0,0 +1,197 @@
+//! Contains a builder which writes elements with balanced start and end tags.
+//!
+//! Content of an element is written through a `ChildWriter`, which can only write whole
+//! elements, so start and end tags are balanced by construction.
+//!
+//! ```rust
+//! use xml::writer::{EventWriter, EmitterConfig};
+//!
+//! let config = EmitterConfig::new().write_document_declaration(false);
+//! let mut w = EventWriter::new_with_config(Vec::new(), config);
+//! w.element("order").attr("id", "3").ns("p", "urn:p").children(|w| {
+//!     try!(w.element("p:line").attr("sku", "A&B").text("2"));
+//!     w.element("note").empty()
+//! }).ok().unwrap();
+//! ```
+
+use name::{Name, IntoName};
+use attribute::Attribute;
+
+use super::{EventWriter, EventWriterResult};
+use super::events::XmlEvent;
+
+/// Collects the name, namespace declarations and attributes of an element.
+///
+/// Nothing is written until one of the finishing methods, `empty()`, `text()` or
+/// `children()`, is called; each of them writes the whole element, including its end tag.
+pub struct ElementBuilder<'a, 'b, W: 'a> {
+    writer: &'a mut EventWriter<W>,
+    name: Name<'b>,
+    namespaces: Vec<(Option<&'b str>, &'b str)>,
+    attributes: Vec<Attribute<'b>>
+}
+
+impl<'a, 'b, W: Writer> ElementBuilder<'a, 'b, W> {
+    #[inline]
+    pub fn new(writer: &'a mut EventWriter<W>, name: Name<'b>) -> ElementBuilder<'a, 'b, W> {
+        ElementBuilder {
+            writer: writer,
+            name: name,
+            namespaces: Vec::new(),
+            attributes: Vec::new()
+        }
+    }
+
+    /// Adds an attribute to the element.
+    pub fn attr<N: IntoName<'b>>(mut self, name: N, value: &'b str) -> ElementBuilder<'a, 'b, W> {
+        self.attributes.push(Attribute::new(name.into_name(), value));
+        self
+    }
+
+    /// Adds a declaration of a prefixed namespace to the element.
+    pub fn ns(mut self, prefix: &'b str, uri: &'b str) -> ElementBuilder<'a, 'b, W> {
+        self.namespaces.push((Some(prefix), uri));
+        self
+    }
+
+    /// Adds a declaration of the default namespace to the element.
+    pub fn default_ns(mut self, uri: &'b str) -> ElementBuilder<'a, 'b, W> {
+        self.namespaces.push((None, uri));
+        self
+    }
+
+    /// Writes the element without content.
+    pub fn empty(mut self) -> EventWriterResult<()> {
+        try!(self.write_start());
+        self.writer.write_end_element()
+    }
+
+    /// Writes the element with the given text as its content.
+    pub fn text(mut self, content: &str) -> EventWriterResult<()> {
+        try!(self.write_start());
+        try!(self.writer.write_characters(content));
+        self.writer.write_end_element()
+    }
+
+    /// Writes the element with the content written by the given closure.
+    ///
+    /// If the closure returns an error, the error is returned and the end tag is not written.
+    pub fn children<F>(mut self, f: F) -> EventWriterResult<()>
+        where F: FnOnce(&mut ChildWriter<W>) -> EventWriterResult<()>
+    {
+        try!(self.write_start());
+        try!(f(&mut ChildWriter { writer: &mut *self.writer }));
+        self.writer.write_end_element()
+    }
+
+    fn write_start(&mut self) -> EventWriterResult<()> {
+        try!(self.writer.write_start_element(self.name));
+        for &(prefix, uri) in self.namespaces.iter() {
+            try!(self.writer.write_namespace(prefix, uri));
+        }
+        for attr in self.attributes.iter() {
+            try!(self.writer.write_attribute(attr.name, attr.value));
+        }
+        Ok(())
+    }
+}
+
+/// Writes the content of an element built with `ElementBuilder::children()`.
+///
+/// Unlike `EventWriter`, it has no methods which write only a start or an end tag.
+pub struct ChildWriter<'a, W: 'a> {
+    writer: &'a mut EventWriter<W>
+}
+
+impl<'a, W: Writer> ChildWriter<'a, W> {
+    /// Returns a builder which writes a child element with the given name.
+    #[inline]
+    pub fn element<'c, 'b, N: IntoName<'b>>(&'c mut self, name: N) -> ElementBuilder<'c, 'b, W> {
+        ElementBuilder::new(&mut *self.writer, name.into_name())
+    }
+
+    /// Writes escaped text.
+    #[inline]
+    pub fn text(&mut self, content: &str) -> EventWriterResult<()> {
+        self.writer.write_characters(content)
+    }
+
+    #[inline]
+    pub fn cdata(&mut self, content: &str) -> EventWriterResult<()> {
+        self.writer.write_cdata(content)
+    }
+
+    #[inline]
+    pub fn comment(&mut self, content: &str) -> EventWriterResult<()> {
+        self.writer.write_comment(content)
+    }
+
+    #[inline]
+    pub fn processing_instruction(&mut self, name: &str, data: Option<&str>) -> EventWriterResult<()> {
+        self.writer.write(XmlEvent::ProcessingInstruction { name: name, data: data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use name::Name;
+    use writer::{EventWriter, EmitterConfig};
+
+    fn writer() -> EventWriter<Vec<u8>> {
+        EventWriter::new_with_config(Vec::new(), EmitterConfig::new().write_document_declaration(false))
+    }
+
+    #[test]
+    fn nested_elements() {
+        let mut w = writer();
+        let id = 3u32.to_string();
+        w.element("order").attr("id", id.as_slice()).ns("p", "urn:p").children(|w| {
+            try!(w.element("p:line").attr("sku", "a<b").text("2"));
+            try!(w.element(Name::qualified("note", "urn:p", Some("p"))).empty());
+            w.text("done")
+        }).ok().unwrap();
+
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(),
+                   "<order xmlns:p=\"urn:p\" id=\"3\"><p:line sku=\"a&lt;b\">2</p:line>\
+                    <p:note />done</order>");
+    }
+
+    #[test]
+    fn other_content() {
+        let mut w = writer();
+        w.element("a").children(|w| {
+            try!(w.cdata("x<y"));
+            try!(w.comment(" c "));
+            w.processing_instruction("pi", Some("d"))
+        }).ok().unwrap();
+
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(),
+                   "<a><![CDATA[x<y]]><!-- c --><?pi d?></a>");
+    }
+
+    #[test]
+    fn children_error_stops_writing() {
+        let mut w = writer();
+        let result = w.element("a").children(|w| {
+            try!(w.element("b").children(|w| w.comment("x--y")));
+            w.text("unreachable")
+        });
+        assert!(result.is_err());
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(), "<a><b");
+    }
+
+    #[test]
+    fn xml_macro() {
+        let mut w = writer();
+        let (id, sku, qty) = (3u32, "a<b", 2u32);
+        xml!(w; order(id = id, "xmlns:p" = "urn:p") {
+            "p:line"(sku = sku) { {qty} }
+            note() {}
+            { "done" }
+        }).ok().unwrap();
+
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(),
+                   "<order id=\"3\" xmlns:p=\"urn:p\"><p:line sku=\"a&lt;b\">2</p:line>\
+                    <note />done</order>");
+    }
+}
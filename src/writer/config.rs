@@ -22,6 +22,9 @@ pub struct EmitterConfig {
     /// Sometimes, however, automatic indentation is undesirable, e.g. when you want to keep
     /// existing layout when processing an existing XML document. Also the indentiation algorithm
     /// is not thoroughly tested. Hence by default it is disabled.
+    ///
+    /// Since the emitter cannot look ahead, elements with mixed content may get whitespace
+    /// inserted into them. To format whole documents, use `writer::formatter` instead.
     pub perform_indent: bool,
 
     /// Whether or not to write XML document declaration at the beginning of a document.
@@ -34,15 +37,47 @@ pub struct EmitterConfig {
     /// Whether or not to convert elements with empty content to empty elements. Default is true.
     ///
     /// This option allows turning elements like `<a></a>` (an element with empty content)
-    /// into `<a />` (an empty element).
+    /// into `<a />` (an empty element). A start tag is finished only when the next event
+    /// is written, so an end element which immediately follows the start element produces
+    /// an empty element.
     pub normalize_empty_elements: bool,
 
+    /// Whether or not to put a space before `/>` in empty elements. Default is true.
+    ///
+    /// When enabled, empty elements are written as `<a />`, otherwise as `<a/>`.
+    pub pad_self_closing: bool,
+
     /// Whether or not to emit CDATA events as plain characters. Default is false.
     ///
     /// This option forces the emitter to convert CDATA events into regular character events,
     /// performing all the necessary escaping beforehand. This may be occasionally useful
     /// for feeding the document into incorrect parsers which do not support CDATA.
-    pub cdata_to_characters: bool
+    pub cdata_to_characters: bool,
+
+    /// Whether or not to fix comment content which cannot be written. Default is false.
+    ///
+    /// Comment content must not contain `--` and must not end with `-`. By default such
+    /// content is an error; when this option is enabled, spaces are inserted between
+    /// consecutive dashes and after a trailing dash instead.
+    pub sanitize_comments: bool,
+
+    /// Whether or not to repair namespace declarations. Default is false.
+    ///
+    /// By default the emitter writes all mappings from the namespace of a `StartElement`
+    /// event as they are. In repairing mode it keeps track of the bindings in scope and
+    /// writes only the declarations which change them; it also declares namespaces of
+    /// element and attribute names which are not bound yet, generating prefixes like
+    /// `ns0` for attributes without a prefix. Names with unbound prefixes and prefixes
+    /// bound to several namespaces on one element are errors.
+    pub repair_namespaces: bool,
+
+    /// Whether or not to check that raw markup is well-formed. Default is false.
+    ///
+    /// When enabled, every `RawMarkup` event is parsed as element content before it is
+    /// written, and ill-formed fragments are errors. Prefixes used in the fragment must be
+    /// declared in it or on the enclosing elements; only predefined and character entities
+    /// can be referenced.
+    pub validate_raw_markup: bool
 }
 
 impl EmitterConfig {
@@ -62,7 +97,11 @@ impl EmitterConfig {
             perform_indent: false,
             write_document_declaration: true,
             normalize_empty_elements: true,
-            cdata_to_characters: false
+            pad_self_closing: true,
+            cdata_to_characters: false,
+            sanitize_comments: false,
+            repair_namespaces: false,
+            validate_raw_markup: false
         }
     }
 }
@@ -73,5 +112,9 @@ gen_setters!(EmitterConfig,
     perform_indent: bool,
     write_document_declaration: bool,
     normalize_empty_elements: bool,
-    cdata_to_characters: bool
+    pad_self_closing: bool,
+    cdata_to_characters: bool,
+    sanitize_comments: bool,
+    repair_namespaces: bool,
+    validate_raw_markup: bool
 );
@@ -3,21 +3,29 @@ use std::iter;
 use std::fmt;
 
 use common;
-use name::Name;
+use common::{is_whitespace_char, is_name_start_char, is_name_char};
+use name::{Name, OwnedName};
 use attribute::Attribute;
 use escape::escape_str;
 use common::XmlVersion;
-use namespace::{NamespaceStack, NamespaceIterable, UriMapping};
+use namespace::{Namespace, NamespaceStack, NamespaceIterable, UriMapping, NS_XML_PREFIX, NS_XMLNS_PREFIX};
 
+use reader::EventReader;
+use reader::events::XmlEvent as ReaderEvent;
 use writer::config::EmitterConfig;
 
+#[derive(Copy, Clone, PartialEq, Show)]
 pub enum EmitterErrorKind {
     IoError,
     DocumentStartAlreadyEmitted,
     UnexpectedEvent,
-    InvalidWhitespaceEvent
+    InvalidWhitespaceEvent,
+    InvalidComment,
+    NamespaceError,
+    InvalidMarkup
 }
 
+#[derive(Clone, PartialEq)]
 pub struct EmitterError {
     kind: EmitterErrorKind,
     message: &'static str,
@@ -63,6 +71,12 @@ pub struct Emitter {
     indent_level: usize,
     indent_stack: Vec<IndentFlags>,
 
+    element_stack: Vec<OwnedName>,
+    root_element_emitted: bool,
+    doctype_emitted: bool,
+    start_tag_open: bool,
+    empty_element_open: bool,
+
     start_document_emitted: bool
 }
 
@@ -71,11 +85,17 @@ impl Emitter {
         Emitter {
             config: config,
 
-            nst: NamespaceStack::empty(),
+            nst: NamespaceStack::default(),
 
             indent_level: 0,
             indent_stack: vec!(IndentFlags::empty()),
 
+            element_stack: Vec::new(),
+            root_element_emitted: false,
+            doctype_emitted: false,
+            start_tag_open: false,
+            empty_element_open: false,
+
             start_document_emitted: false
         }
     }
@@ -126,6 +146,13 @@ impl Emitter {
         & self.nst
     }
 
+    /// Returns the number of elements which are open, not counting an empty element
+    /// which will be closed by the next event.
+    #[inline]
+    pub fn element_depth(&self) -> usize {
+        self.element_stack.len() - if self.empty_element_open { 1 } else { 0 }
+    }
+
     #[inline]
     fn wrote_text(&self) -> bool {
         self.indent_stack.last().unwrap().contains(WROTE_TEXT)
@@ -160,7 +187,8 @@ impl Emitter {
     }
 
     fn before_markup<W: Writer>(&mut self, target: &mut W) -> EmitterResult<()> {
-        if !self.wrote_text() && (self.indent_level > 0 || self.wrote_markup()) {
+        if self.config.perform_indent && !self.wrote_text() &&
+           (self.indent_level > 0 || self.wrote_markup()) {
             let indent_level = self.indent_level;
             try!(self.write_newline(target, indent_level));
             if self.indent_level > 0 && self.config.indent_string.len() > 0 {
@@ -186,7 +214,8 @@ impl Emitter {
     }
 
     fn before_end_element<W: Writer>(&mut self, target: &mut W) -> EmitterResult<()> {
-        if self.indent_level > 0 && self.wrote_markup() && !self.wrote_text() {
+        if self.config.perform_indent && self.indent_level > 0 &&
+           self.wrote_markup() && !self.wrote_text() {
             let indent_level = self.indent_level;
             self.write_newline(target, indent_level - 1)
         } else {
@@ -206,6 +235,54 @@ impl Emitter {
         self.set_wrote_text();
     }
 
+    /// Finishes a start tag which has been left open to be possibly turned into
+    /// an empty element or to receive more attributes.
+    fn close_start_tag<W: Writer>(&mut self, target: &mut W) -> EmitterResult<()> {
+        if !self.start_tag_open {
+            return Ok(());
+        }
+        self.start_tag_open = false;
+
+        if self.empty_element_open {
+            self.empty_element_open = false;
+            self.element_stack.pop();
+            self.nst.pop();
+            let end = self.empty_element_end();
+            io_try!(target.write_str(end));
+            self.after_end_element();
+            Ok(())
+        } else {
+            io_wrap(write!(target, ">"))
+        }
+    }
+
+    fn check_start_tag_open(&self) -> EmitterResult<()> {
+        if self.start_tag_open {
+            Ok(())
+        } else {
+            Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Attributes and namespaces can only be written right after a start element"
+            ))
+        }
+    }
+
+    #[inline]
+    fn empty_element_end(&self) -> &'static str {
+        if self.config.pad_self_closing { " />" } else { "/>" }
+    }
+
+    fn check_text(&self, content: &str) -> EmitterResult<()> {
+        if self.element_depth() == 0 && !content.chars().all(is_whitespace_char) {
+            Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Text is only allowed inside the root element"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn emit_start_document<W: Writer>(&mut self, target: &mut W, 
                                           version: XmlVersion, 
                                           encoding: &str, 
@@ -244,6 +321,7 @@ impl Emitter {
                                                   name: &str,
                                                   data: Option<&str>) -> EmitterResult<()> {
         try!(self.check_document_started(target));
+        try!(self.close_start_tag(target));
 
         wrapped_with!(self; before_markup(target) and after_markup,
             io_chain!(
@@ -264,17 +342,175 @@ impl Emitter {
               N: NamespaceIterable<'a, Iter=I>,
               I: Iterator<Item=UriMapping<'a>>
     {
+        if self.element_depth() == 0 && self.root_element_emitted {
+            return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Only one root element is allowed"
+            ));
+        }
+
         try!(self.check_document_started(target));
+        try!(self.close_start_tag(target));
 
         try!(self.before_start_element(target));
 
+        if self.config.repair_namespaces {
+            return self.emit_repaired_start_element(target, name, attributes, namespace);
+        }
+
         io_try!(write!(target, "<{}", name.to_repr()));
+        self.element_stack.push(name.to_owned());
+        self.root_element_emitted = true;
 
+        // declarations are tracked in both modes, e.g. for validation of raw markup
+        self.nst.push_empty();
+        for (prefix, uri) in namespace.uri_mappings() {
+            self.nst.put(prefix.map(|p| p.to_string()), uri.to_string());
+        }
         try!(self.emit_namespace_attributes(target, namespace));
 
         self.emit_attributes(target, attributes)
     }
 
+    /// Writes a start tag with only those namespace declarations which are not in scope yet,
+    /// binding namespaces of the element and attribute names as needed.
+    fn emit_repaired_start_element<'a, 'b, W, N, I>(&mut self, target: &mut W,
+                                                    name: Name<'b>,
+                                                    attributes: &[Attribute],
+                                                    namespace: &'a N) -> EmitterResult<()>
+        where W: Writer,
+              N: NamespaceIterable<'a, Iter=I>,
+              I: Iterator<Item=UriMapping<'a>>
+    {
+        self.nst.push_empty();
+        let (name, attribute_names) = match self.repair_names(name, attributes, namespace) {
+            Ok(names) => names,
+            Err(e) => {
+                self.nst.pop();
+                return Err(e);
+            }
+        };
+
+        io_try!(write!(target, "<{}", name.to_repr()));
+        for (prefix, uri) in self.nst.peek().uri_mappings() {
+            io_try!(write_namespace_declaration(target, prefix, uri));
+        }
+        for (attr, name) in attributes.iter().zip(attribute_names.iter()) {
+            io_try!(write!(target, " {}=\"{}\"", name.to_repr(), escape_str(attr.value)));
+        }
+
+        self.element_stack.push(name);
+        self.root_element_emitted = true;
+        Ok(())
+    }
+
+    fn repair_names<'a, N, I>(&mut self, name: Name, attributes: &[Attribute],
+                              namespace: &'a N) -> EmitterResult<(OwnedName, Vec<OwnedName>)>
+        where N: NamespaceIterable<'a, Iter=I>,
+              I: Iterator<Item=UriMapping<'a>>
+    {
+        // explicit declarations are only written if they change the bindings in scope
+        for (prefix, uri) in namespace.uri_mappings() {
+            match prefix {
+                Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) => continue,
+                _ => {}
+            }
+            let in_scope = self.nst.get(&prefix.map(|p| p.to_string())) == Some(uri);
+            if !in_scope {
+                try!(self.bind(prefix, uri));
+            }
+        }
+
+        let name = try!(self.repair_name(name, false));
+        let mut attribute_names = Vec::with_capacity(attributes.len());
+        for attr in attributes.iter() {
+            attribute_names.push(try!(self.repair_name(attr.name, true)));
+        }
+        Ok((name, attribute_names))
+    }
+
+    /// Returns the name which should be written for the given element or attribute name,
+    /// binding its namespace on the current element if it is not in scope.
+    fn repair_name(&mut self, name: Name, attribute: bool) -> EmitterResult<OwnedName> {
+        let mut result = name.to_owned();
+
+        let uri = match name.namespace {
+            Some(uri) => uri,
+            None => {
+                match name.prefix {
+                    Some(prefix) => if self.nst.get(&Some(prefix.to_string())).is_none() {
+                        return Err(error(
+                            EmitterErrorKind::NamespaceError,
+                            "Name prefix is not bound to a namespace"
+                        ));
+                    },
+                    // an unprefixed element without a namespace must not be in a default namespace
+                    None if !attribute => {
+                        let in_default = self.nst.get(&None).map(|uri| !uri.is_empty()).unwrap_or(false);
+                        if in_default {
+                            try!(self.bind(None, ""));
+                        }
+                    }
+                    None => {}
+                }
+                return Ok(result);
+            }
+        };
+
+        if let Some(prefix) = name.prefix {
+            let in_scope = self.nst.get(&Some(prefix.to_string())) == Some(uri);
+            if !in_scope {
+                try!(self.bind(Some(prefix), uri));
+            }
+            return Ok(result);
+        }
+
+        // reuse a binding which is in scope; attributes cannot use the default namespace
+        let existing = self.nst.uri_mappings()
+            .find(|&(p, u)| u == uri && (p.is_some() || !attribute))
+            .map(|(p, _)| p.map(|p| p.to_string()));
+        match existing {
+            Some(prefix) => result.prefix = prefix,
+            None if !attribute => try!(self.bind(None, uri)),
+            None => {
+                let prefix = self.generate_prefix();
+                try!(self.bind(Some(prefix.as_slice()), uri));
+                result.prefix = Some(prefix);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Declares a namespace on the current element; binding a prefix which is already
+    /// bound on the current element to another URI is an error.
+    fn bind(&mut self, prefix: Option<&str>, uri: &str) -> EmitterResult<()> {
+        let prefix = prefix.map(|p| p.to_string());
+        let bound = self.nst.peek().get(&prefix).map(|u| u == uri);
+        match bound {
+            Some(true) => Ok(()),
+            Some(false) => Err(error(
+                EmitterErrorKind::NamespaceError,
+                "Prefix is bound to different namespaces on the same element"
+            )),
+            None => {
+                self.nst.put(prefix, uri.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the first prefix of the form `nsN` which is not bound in scope.
+    fn generate_prefix(&self) -> String {
+        let mut n: usize = 0;
+        loop {
+            let prefix = format!("ns{}", n);
+            if self.nst.get(&Some(prefix.clone())).is_none() {
+                return prefix;
+            }
+            n += 1;
+        }
+    }
+
     pub fn emit_empty_element<'a, 'b, W, N, I>(&mut self, target: &mut W,
                                                name: Name<'b>, 
                                                attributes: &[Attribute],
@@ -284,8 +520,14 @@ impl Emitter {
               I: Iterator<Item=UriMapping<'a>>
     {
         try!(self.emit_start_element_initial(target, name, attributes, namespace));
+        self.after_start_element();
+        self.element_stack.pop();
+        self.nst.pop();
 
-        io_wrap(write!(target, "/>"))
+        let end = self.empty_element_end();
+        io_try!(target.write_str(end));
+        self.after_end_element();
+        Ok(())
     }
 
     pub fn emit_start_element<'a, 'b, W, N, I>(&mut self, target: &mut W,
@@ -297,8 +539,71 @@ impl Emitter {
               I: Iterator<Item=UriMapping<'a>>
     {
         try!(self.emit_start_element_initial(target, name, attributes, namespace));
+        self.after_start_element();
 
-        io_wrap(write!(target, ">"))
+        if self.config.normalize_empty_elements {
+            // the tag is finished by the next event
+            self.start_tag_open = true;
+            Ok(())
+        } else {
+            io_wrap(write!(target, ">"))
+        }
+    }
+
+    /// Writes the beginning of a start tag and leaves it open, so attributes and namespace
+    /// declarations can be added with `emit_attribute` and `emit_namespace_declaration`
+    /// until the next event.
+    pub fn emit_start_tag<W: Writer>(&mut self, target: &mut W, name: Name) -> EmitterResult<()> {
+        try!(self.emit_start_element_initial(target, name, &[], &Namespace::empty()));
+        self.after_start_element();
+        self.start_tag_open = true;
+        Ok(())
+    }
+
+    /// Same as `emit_start_tag`, but the element is finished as an empty element
+    /// by the next event.
+    pub fn emit_empty_start_tag<W: Writer>(&mut self, target: &mut W, name: Name) -> EmitterResult<()> {
+        try!(self.emit_start_tag(target, name));
+        self.empty_element_open = true;
+        Ok(())
+    }
+
+    pub fn emit_attribute<W: Writer>(&mut self, target: &mut W,
+                                     name: Name, value: &str) -> EmitterResult<()> {
+        try!(self.check_start_tag_open());
+
+        if !self.config.repair_namespaces {
+            return io_wrap(write!(target, " {}=\"{}\"", name.to_repr(), escape_str(value)));
+        }
+
+        let declared = self.nst.peek().clone();
+        let name = try!(self.repair_name(name, true));
+        for (prefix, uri) in self.nst.peek().uri_mappings() {
+            if declared.get(&prefix.map(|p| p.to_string())).is_none() {
+                io_try!(write_namespace_declaration(target, prefix, uri));
+            }
+        }
+        io_wrap(write!(target, " {}=\"{}\"", name.to_repr(), escape_str(value)))
+    }
+
+    pub fn emit_namespace_declaration<W: Writer>(&mut self, target: &mut W,
+                                                 prefix: Option<&str>,
+                                                 uri: &str) -> EmitterResult<()> {
+        try!(self.check_start_tag_open());
+
+        if self.config.repair_namespaces {
+            match prefix {
+                Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) => return Ok(()),
+                _ => {}
+            }
+            if self.nst.get(&prefix.map(|p| p.to_string())) == Some(uri) {
+                return Ok(());
+            }
+            try!(self.bind(prefix, uri));
+        } else {
+            self.nst.put(prefix.map(|p| p.to_string()), uri.to_string());
+        }
+        io_wrap(write_namespace_declaration(target, prefix, uri))
     }
 
     pub fn emit_namespace_attributes<'a, W, N, I>(&mut self, target: &mut W,
@@ -328,13 +633,66 @@ impl Emitter {
     }
 
     pub fn emit_end_element<W: Writer>(&mut self, target: &mut W,
-                                       name: Name) -> EmitterResult<()> {
+                                       name: Option<Name>) -> EmitterResult<()> {
+        if self.empty_element_open {
+            try!(self.close_start_tag(target));
+        }
+
+        let open = match self.element_stack.pop() {
+            Some(open) => open,
+            None => return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "End element is emitted without an open element"
+            ))
+        };
+        if let Some(name) = name {
+            // in repairing mode the written prefix can differ from the one in the event
+            let same_name = name.local_name == open.local_name.as_slice() &&
+                (name.prefix == open.prefix_as_ref() ||
+                 name.namespace.is_some() && name.namespace == open.namespace_as_ref());
+            if !same_name {
+                self.element_stack.push(open);
+                return Err(error(
+                    EmitterErrorKind::UnexpectedEvent,
+                    "End element name does not match the open element"
+                ));
+            }
+        }
+        self.nst.pop();
+
+        if self.start_tag_open {
+            self.start_tag_open = false;
+            if self.config.normalize_empty_elements {
+                let end = self.empty_element_end();
+                io_try!(target.write_str(end));
+                self.after_end_element();
+                return Ok(());
+            }
+            io_try!(write!(target, ">"));
+        }
+
         wrapped_with!(self; before_end_element(target) and after_end_element,
-            io_wrap(write!(target, "</{}>", name.to_repr()))
+            io_wrap(write!(target, "</{}>", open.to_repr()))
         )
     }
 
+    /// Finishes all open elements.
+    pub fn emit_end_document<W: Writer>(&mut self, target: &mut W) -> EmitterResult<()> {
+        try!(self.close_start_tag(target));
+        while !self.element_stack.is_empty() {
+            try!(self.emit_end_element(target, None));
+        }
+        Ok(())
+    }
+
     pub fn emit_cdata<W: Writer>(&mut self, target: &mut W, content: &str) -> EmitterResult<()> {
+        if self.element_depth() == 0 {
+            return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "CDATA is only allowed inside the root element"
+            ));
+        }
+        try!(self.close_start_tag(target));
         if self.config.cdata_to_characters {
             self.emit_characters(target, content)
         } else {
@@ -348,12 +706,388 @@ impl Emitter {
 
     pub fn emit_characters<W: Writer>(&mut self, target: &mut W,
                                       content: &str) -> EmitterResult<()> {
+        try!(self.check_text(content));
+        try!(self.close_start_tag(target));
         io_try!(target.write_str(escape_str(content).as_slice()));
         self.after_text();
         Ok(())
     }
 
+    pub fn emit_doctype<W: Writer>(&mut self, target: &mut W, content: &str) -> EmitterResult<()> {
+        if self.doctype_emitted || self.root_element_emitted {
+            return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Document type declaration is only allowed once, before the root element"
+            ));
+        }
+        try!(self.check_document_started(target));
+        self.doctype_emitted = true;
+
+        wrapped_with!(self; before_markup(target) and after_markup,
+            io_wrap(write!(target, "<!DOCTYPE {}>", content))
+        )
+    }
+
+    pub fn emit_entity_reference<W: Writer>(&mut self, target: &mut W, name: &str) -> EmitterResult<()> {
+        if self.element_depth() == 0 {
+            return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Entity references are only allowed inside the root element"
+            ));
+        }
+        if !is_entity_name(name) {
+            return Err(error(
+                EmitterErrorKind::InvalidMarkup,
+                "Entity reference name is not a valid name or character reference"
+            ));
+        }
+        try!(self.close_start_tag(target));
+        io_try!(write!(target, "&{};", name));
+        self.after_text();
+        Ok(())
+    }
+
+    /// Writes the content as is; a fragment which starts with a tag is treated
+    /// as markup for indentation purposes, any other fragment is treated as text.
+    ///
+    /// Raw markup is only allowed inside the root element, since elements in it
+    /// are not tracked and could otherwise form additional root elements.
+    pub fn emit_raw_markup<W: Writer>(&mut self, target: &mut W, content: &str) -> EmitterResult<()> {
+        if self.element_depth() == 0 {
+            return Err(error(
+                EmitterErrorKind::UnexpectedEvent,
+                "Raw markup is only allowed inside the root element"
+            ));
+        }
+        if self.config.validate_raw_markup && !self.is_well_formed(content) {
+            return Err(error(
+                EmitterErrorKind::InvalidMarkup,
+                "Raw markup is not well-formed"
+            ));
+        }
+        try!(self.check_document_started(target));
+        try!(self.close_start_tag(target));
+
+        if content.starts_with("<") {
+            wrapped_with!(self; before_markup(target) and after_markup,
+                io_wrap(target.write_str(content))
+            )
+        } else {
+            io_try!(target.write_str(content));
+            self.after_text();
+            Ok(())
+        }
+    }
+
+    /// Checks whether the fragment can be the content of an element in the current scope.
+    fn is_well_formed(&self, content: &str) -> bool {
+        let mut wrapped = String::from_str("<fragment");
+        for (prefix, uri) in self.nst.squash().uri_mappings() {
+            match prefix {
+                Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) | None => {}
+                Some(prefix) => wrapped.push_str(
+                    format!(" xmlns:{}=\"{}\"", prefix, escape_str(uri)).as_slice()
+                )
+            }
+        }
+        wrapped.push('>');
+        wrapped.push_str(content);
+        wrapped.push_str("</fragment>");
+
+        // the reader accepts several root elements, so the wrapper must be
+        // tracked here: the fragment must not close it before its own end tag
+        let mut reader = EventReader::new_from_str_slice(wrapped.as_slice());
+        let mut depth: usize = 0;
+        let mut closed = false;
+        loop {
+            match reader.next() {
+                ReaderEvent::EndDocument => return closed,
+                ReaderEvent::Error(_) => return false,
+                _ if closed => return false,
+                ReaderEvent::StartElement { .. } => depth += 1,
+                ReaderEvent::EndElement { .. } => {
+                    depth -= 1;
+                    closed = depth == 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
     pub fn emit_comment<W: Writer>(&mut self, target: &mut W, content: &str) -> EmitterResult<()> {
-        Ok(())  // TODO: proper write
+        let sanitized;
+        let content = if content.contains("--") || content.ends_with("-") {
+            if !self.config.sanitize_comments {
+                return Err(error(
+                    EmitterErrorKind::InvalidComment,
+                    "Comment content contains \"--\" or ends with \"-\""
+                ));
+            }
+            sanitized = sanitize_comment(content);
+            sanitized.as_slice()
+        } else {
+            content
+        };
+
+        try!(self.check_document_started(target));
+        try!(self.close_start_tag(target));
+
+        wrapped_with!(self; before_markup(target) and after_markup,
+            io_chain!(
+                target.write_str("<!--"),
+                target.write_str(content),
+                target.write_str("-->")
+            )
+        )
+    }
+}
+
+fn write_namespace_declaration<W: Writer>(target: &mut W, prefix: Option<&str>,
+                                          uri: &str) -> io::IoResult<()> {
+    match prefix {
+        Some(prefix) => write!(target, " xmlns:{}=\"{}\"", prefix, escape_str(uri)),
+        None => write!(target, " xmlns=\"{}\"", escape_str(uri))
+    }
+}
+
+/// Checks whether the given string is an entity name or a character reference
+/// like `#160` or `#xA0`.
+fn is_entity_name(name: &str) -> bool {
+    if name.starts_with("#x") {
+        let digits = name.slice_from(2);
+        !digits.is_empty() && digits.chars().all(|c| c.is_digit(16))
+    } else if name.starts_with("#") {
+        let digits = name.slice_from(1);
+        !digits.is_empty() && digits.chars().all(|c| c.is_digit(10))
+    } else {
+        let mut chars = name.chars();
+        chars.next().map(is_name_start_char).unwrap_or(false) && chars.all(is_name_char)
+    }
+}
+
+/// Separates consecutive dashes and a trailing dash with spaces, so the content can be
+/// written inside a comment.
+fn sanitize_comment(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut last_dash = false;
+    for c in content.chars() {
+        if c == '-' && last_dash {
+            result.push(' ');
+        }
+        result.push(c);
+        last_dash = c == '-';
+    }
+    if last_dash {
+        result.push(' ');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+
+    use name::Name;
+    use attribute::Attribute;
+    use namespace::Namespace;
+    use writer::config::EmitterConfig;
+    use super::Emitter;
+
+    fn written(output: &Vec<u8>) -> &str {
+        str::from_utf8(output.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn comments() {
+        let config = EmitterConfig::new().write_document_declaration(false);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        emitter.emit_comment(&mut output, " a comment ").ok().unwrap();
+        assert_eq!(written(&output), "<!-- a comment -->");
+
+        assert!(emitter.emit_comment(&mut output, "a -- b").is_err());
+        assert!(emitter.emit_comment(&mut output, "trailing-").is_err());
+
+        let config = EmitterConfig::new().write_document_declaration(false).sanitize_comments(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        emitter.emit_comment(&mut output, "a --- b-").ok().unwrap();
+        assert_eq!(written(&output), "<!--a - - - b- -->");
+    }
+
+    #[test]
+    fn well_formedness() {
+        let config = EmitterConfig::new().write_document_declaration(false);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let namespace = Namespace::empty();
+
+        assert!(emitter.emit_characters(&mut output, "text").is_err());
+        emitter.emit_characters(&mut output, "\n").ok().unwrap();
+        emitter.emit_start_element(&mut output, Name::local("a"), &[], &namespace).ok().unwrap();
+        emitter.emit_start_element(&mut output, Name::local("b"), &[], &namespace).ok().unwrap();
+        assert!(emitter.emit_end_element(&mut output, Some(Name::local("a"))).is_err());
+        emitter.emit_end_element(&mut output, Some(Name::local("b"))).ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+
+        assert!(emitter.emit_end_element(&mut output, None).is_err());
+        assert!(emitter.emit_start_element(&mut output, Name::local("c"), &[], &namespace).is_err());
+        assert!(emitter.emit_characters(&mut output, "tail").is_err());
+        assert!(emitter.emit_cdata(&mut output, "tail").is_err());
+        emitter.emit_comment(&mut output, " end ").ok().unwrap();
+
+        assert_eq!(written(&output), "\n<a><b /></a><!-- end -->");
+    }
+
+    #[test]
+    fn empty_elements() {
+        let namespace = Namespace::empty();
+        let emit = |&: config: EmitterConfig| {
+            let mut emitter = Emitter::new(config.write_document_declaration(false));
+            let mut output = Vec::new();
+            emitter.emit_start_element(&mut output, Name::local("a"), &[], &namespace).ok().unwrap();
+            emitter.emit_start_element(&mut output, Name::local("b"), &[], &namespace).ok().unwrap();
+            emitter.emit_end_element(&mut output, None).ok().unwrap();
+            emitter.emit_start_element(&mut output, Name::local("c"), &[], &namespace).ok().unwrap();
+            emitter.emit_characters(&mut output, "").ok().unwrap();
+            emitter.emit_end_element(&mut output, None).ok().unwrap();
+            emitter.emit_end_element(&mut output, None).ok().unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(emit(EmitterConfig::new()).as_slice(), "<a><b /><c></c></a>");
+        assert_eq!(emit(EmitterConfig::new().pad_self_closing(false)).as_slice(), "<a><b/><c></c></a>");
+        assert_eq!(emit(EmitterConfig::new().normalize_empty_elements(false)).as_slice(),
+                   "<a><b></b><c></c></a>");
+        assert_eq!(emit(EmitterConfig::new().perform_indent(true)).as_slice(),
+                   "<a>\n  <b />\n  <c></c>\n</a>");
+    }
+
+    #[test]
+    fn repairing_namespaces() {
+        let config = EmitterConfig::new()
+            .write_document_declaration(false)
+            .repair_namespaces(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+
+        let mut declared = Namespace::empty();
+        declared.put(Some("p"), "urn:p");
+        let empty = Namespace::empty();
+
+        emitter.emit_start_element(&mut output, Name::qualified("root", "urn:r", None), &[], &declared)
+            .ok().unwrap();
+        // the declaration is in scope already
+        emitter.emit_start_element(&mut output, Name::qualified("a", "urn:p", Some("p")), &[], &declared)
+            .ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+        // unprefixed attributes in a namespace get a generated prefix
+        let attributes = [Attribute::new(Name::qualified("id", "urn:q", None), "1")];
+        emitter.emit_start_element(&mut output, Name::local("b"), &attributes, &empty).ok().unwrap();
+        emitter.emit_end_element(&mut output, Some(Name::local("b"))).ok().unwrap();
+        emitter.emit_end_element(&mut output, Some(Name::qualified("root", "urn:r", None))).ok().unwrap();
+
+        let written = written(&output);
+        assert!(written.starts_with("<root "));
+        assert!(written.contains(" xmlns=\"urn:r\""));
+        assert!(written.contains(" xmlns:p=\"urn:p\""));
+        assert!(written.contains("<p:a />"));
+        assert!(written.contains("<b xmlns=\"\""));
+        assert!(written.contains(" xmlns:ns0=\"urn:q\""));
+        assert!(written.contains(" ns0:id=\"1\" />"));
+        assert!(written.ends_with("</root>"));
+    }
+
+    #[test]
+    fn repairing_errors() {
+        let config = EmitterConfig::new()
+            .write_document_declaration(false)
+            .repair_namespaces(true);
+        let empty = Namespace::empty();
+
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let unbound = Name { local_name: "a", namespace: None, prefix: Some("q") };
+        assert!(emitter.emit_start_element(&mut output, unbound, &[], &empty).is_err());
+
+        let config = EmitterConfig::new()
+            .write_document_declaration(false)
+            .repair_namespaces(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let attributes = [Attribute::new(Name::qualified("b", "urn:2", Some("p")), "1")];
+        let name = Name::qualified("a", "urn:1", Some("p"));
+        assert!(emitter.emit_start_element(&mut output, name, &attributes, &empty).is_err());
+    }
+
+    #[test]
+    fn doctype_entities_and_raw_markup() {
+        let config = EmitterConfig::new().perform_indent(true).validate_raw_markup(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let namespace = Namespace::empty();
+
+        assert!(emitter.emit_entity_reference(&mut output, "nbsp").is_err());
+        emitter.emit_doctype(&mut output, "html").ok().unwrap();
+        assert!(emitter.emit_doctype(&mut output, "html").is_err());
+        assert!(emitter.emit_raw_markup(&mut output, "<a/>").is_err());
+        emitter.emit_start_element(&mut output, Name::local("html"), &[], &namespace).ok().unwrap();
+        emitter.emit_raw_markup(&mut output, "<head><title>A &amp; B</title></head>").ok().unwrap();
+        assert!(emitter.emit_raw_markup(&mut output, "<body>").is_err());
+        assert!(emitter.emit_raw_markup(&mut output, "<p:body/>").is_err());
+        emitter.emit_start_element(&mut output, Name::local("p"), &[], &namespace).ok().unwrap();
+        emitter.emit_characters(&mut output, "a").ok().unwrap();
+        emitter.emit_entity_reference(&mut output, "nbsp").ok().unwrap();
+        emitter.emit_entity_reference(&mut output, "#xA0").ok().unwrap();
+        assert!(emitter.emit_entity_reference(&mut output, "1a").is_err());
+        assert!(emitter.emit_entity_reference(&mut output, "#x").is_err());
+        emitter.emit_raw_markup(&mut output, "b").ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+        assert!(emitter.emit_doctype(&mut output, "html").is_err());
+        assert!(emitter.emit_raw_markup(&mut output, "<a/>").is_err());
+
+        assert_eq!(written(&output),
+                   "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                    <!DOCTYPE html>\n\
+                    <html>\n  \
+                    <head><title>A &amp; B</title></head>\n  \
+                    <p>a&nbsp;&#xA0;b</p>\n\
+                    </html>");
+    }
+
+    #[test]
+    fn raw_markup_uses_declared_prefixes() {
+        let config = EmitterConfig::new().write_document_declaration(false).validate_raw_markup(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let mut declared = Namespace::empty();
+        declared.put(Some("p"), "urn:p");
+        let empty = Namespace::empty();
+
+        emitter.emit_start_element(&mut output, Name::local("a"), &[], &declared).ok().unwrap();
+        emitter.emit_start_element(&mut output, Name::local("b"), &[], &empty).ok().unwrap();
+        emitter.emit_raw_markup(&mut output, "<p:c/>").ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+
+        assert_eq!(written(&output), "<a xmlns:p=\"urn:p\"><b><p:c/></b></a>");
+    }
+
+    #[test]
+    fn raw_markup_cannot_close_its_element() {
+        let config = EmitterConfig::new().write_document_declaration(false).validate_raw_markup(true);
+        let mut emitter = Emitter::new(config);
+        let mut output = Vec::new();
+        let namespace = Namespace::empty();
+
+        emitter.emit_start_element(&mut output, Name::local("a"), &[], &namespace).ok().unwrap();
+        assert!(emitter.emit_raw_markup(&mut output, "</fragment><fragment>").is_err());
+        assert!(emitter.emit_raw_markup(&mut output, "</fragment><!-- --><fragment>").is_err());
+        assert!(emitter.emit_raw_markup(&mut output, "</fragment>").is_err());
+        emitter.emit_raw_markup(&mut output, "<b></b><c/>").ok().unwrap();
+        emitter.emit_end_element(&mut output, None).ok().unwrap();
+
+        assert_eq!(written(&output), "<a><b></b><c/></a>");
     }
 }
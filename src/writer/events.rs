@@ -37,6 +37,13 @@ pub enum XmlEvent<'a> {
         data: Option<&'a str>
     },
 
+    /// Denotes a document type declaration.
+    ///
+    /// Contains everything between `<!DOCTYPE ` and `>`, e.g. `html` or
+    /// `note SYSTEM "note.dtd"`; it is written as is. This event is only allowed once,
+    /// before the root element.
+    Doctype(&'a str),
+
     /// Denotes a beginning of an XML element.
     ///
     /// This event is emitted after parsing opening tags or after parsing bodiless tags. In the
@@ -57,13 +64,13 @@ pub enum XmlEvent<'a> {
         namespace: &'a Namespace,
     },
 
-    /// Denotes an end of an XML document.
+    /// Denotes an end of an XML element.
     ///
-    /// This event is emitted after parsing closing tags or after parsing bodiless tags. In the
-    /// latter case it is emitted immediately after corresponding `StartElement` event.
+    /// The emitter keeps track of open elements, so the name is optional; if it is present,
+    /// it must match the name of the innermost open element.
     EndElement {
-        /// Qualified name of the element.
-        name: Name<'a>
+        /// Qualified name of the element, or `None` to close the innermost open element.
+        name: Option<Name<'a>>
     },
 
     /// Denotes CDATA content.
@@ -87,5 +94,19 @@ pub enum XmlEvent<'a> {
     ///
     /// It is possible to configure a parser to trim leading and trailing whitespace for this event.
     /// See `reaer::ParserConfiguration` structure for more information.
-    Characters(&'a str)
+    Characters(&'a str),
+
+    /// Denotes an entity reference which is written unexpanded.
+    ///
+    /// Contains the name of the entity, so `EntityReference("nbsp")` is written as `&nbsp;`.
+    /// Character references like `#160` are accepted as well.
+    EntityReference(&'a str),
+
+    /// Denotes a pre-serialized piece of XML which is copied to the output verbatim.
+    ///
+    /// The emitter does not look inside the fragment, so elements in it are not taken into
+    /// account when checking the well-formedness of the document; the fragment itself can
+    /// be checked by enabling `validate_raw_markup` option of `EmitterConfig`. For the same
+    /// reason raw markup is only allowed inside the root element.
+    RawMarkup(&'a str)
 }
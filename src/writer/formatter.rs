@@ -0,0 +1,337 @@
+//! Contains a formatter which writes documents with stable indentation.
+//!
+//! Unlike the indentation performed by the emitter, which works on a stream of events,
+//! the formatter works on a `dom::Document`, so it knows the content of every element
+//! before writing its start tag. Elements are written according to their content:
+//!
+//! * elements without children are written as empty elements;
+//! * elements which contain only text are written on one line, with the text unchanged;
+//! * elements which contain elements, comments or processing instructions and only
+//!   whitespace text are written with each child on its own indented line; the
+//!   whitespace between children is replaced;
+//! * elements with mixed content, and elements with `xml:space="preserve"`, are written
+//!   with their whole subtree on the same line, so whitespace in them is never altered.
+//!
+//! Start tags whose attributes do not fit into the configured width are wrapped, one
+//! attribute per line. Namespace declarations are written only where they change the
+//! bindings in scope, sorted by prefix. Formatting an already formatted document gives
+//! the same output.
+
+use std::iter;
+use std::io::IoResult;
+
+use common::is_whitespace_char;
+use escape::escape_str;
+use namespace::{Namespace, NamespaceIterable, NS_XML_PREFIX, NS_XMLNS_PREFIX, NS_XML_URI};
+use dom::{Document, Element, Node, Text, CData, Comment, ProcessingInstruction};
+
+/// A unit of indentation.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum Indent {
+    /// The given number of spaces.
+    Spaces(usize),
+
+    /// A single tab character.
+    Tab
+}
+
+/// Formatter configuration structure.
+pub struct FormatterConfig {
+    /// A single level of indentation. Default is `Indent::Spaces(2)`.
+    pub indent: Indent,
+
+    /// Line separator. Default is `"\n"`.
+    pub line_separator: String,
+
+    /// Maximal length of a start tag line, in characters, before its attributes are
+    /// wrapped onto separate lines. Default is 80; zero disables wrapping.
+    ///
+    /// Indentation is counted, with a tab counted as one character. Start tags with
+    /// a single attribute are never wrapped.
+    pub max_width: usize,
+
+    /// Whether or not to sort attributes by their qualified names. Default is false.
+    pub sort_attributes: bool,
+
+    /// Whether or not to put a space before `/>` in empty elements. Default is true.
+    pub pad_self_closing: bool
+}
+
+impl FormatterConfig {
+    /// Creates a formatter configuration with default values.
+    pub fn new() -> FormatterConfig {
+        FormatterConfig {
+            indent: Indent::Spaces(2),
+            line_separator: "\n".to_string(),
+            max_width: 80,
+            sort_attributes: false,
+            pad_self_closing: true
+        }
+    }
+}
+
+gen_setters!(FormatterConfig,
+    indent: Indent,
+    line_separator: String,
+    max_width: usize,
+    sort_attributes: bool,
+    pad_self_closing: bool
+);
+
+/// How the content of an element is laid out.
+#[derive(Copy, PartialEq)]
+enum Layout {
+    Empty,
+    Text,
+    Block,
+    Inline
+}
+
+/// Writes documents and elements according to a `FormatterConfig`.
+pub struct Formatter {
+    config: FormatterConfig
+}
+
+impl Formatter {
+    #[inline]
+    pub fn new(config: FormatterConfig) -> Formatter {
+        Formatter { config: config }
+    }
+
+    /// Writes the document declaration and each top-level node on its own line,
+    /// followed by a line separator.
+    pub fn format_document<W: Writer>(&self, document: &Document, target: &mut W) -> IoResult<()> {
+        try!(write!(target, "<?xml version=\"{}\" encoding=\"{}\"", document.version, document.encoding));
+        if let Some(standalone) = document.standalone {
+            try!(write!(target, " standalone=\"{}\"", if standalone { "yes" } else { "no" }));
+        }
+        try!(target.write_str("?>"));
+
+        let scope = Namespace::empty();
+        for child in document.children.iter().filter(|n| !is_whitespace(*n)) {
+            try!(target.write_str(self.config.line_separator.as_slice()));
+            try!(self.write_node(child, 0, &scope, false, target));
+        }
+        target.write_str(self.config.line_separator.as_slice())
+    }
+
+    /// Writes the given element as if it were at the top level of a document.
+    pub fn format_element<W: Writer>(&self, element: &Element, target: &mut W) -> IoResult<()> {
+        self.write_element(element, 0, &Namespace::empty(), false, target)
+    }
+
+    fn write_node<W: Writer>(&self, node: &Node, level: usize, scope: &Namespace,
+                             inline: bool, target: &mut W) -> IoResult<()> {
+        match *node {
+            Node::Element(ref e) => self.write_element(e, level, scope, inline, target),
+            Node::Text(Text(ref data)) => target.write_str(escape_str(data.as_slice()).as_slice()),
+            Node::CData(CData(ref data)) => write!(target, "<![CDATA[{}]]>", data),
+            Node::Comment(Comment(ref data)) => write!(target, "<!--{}-->", data),
+            Node::ProcessingInstruction(ProcessingInstruction { ref name, ref data }) => match *data {
+                Some(ref data) => write!(target, "<?{} {}?>", name, data),
+                None => write!(target, "<?{}?>", name)
+            }
+        }
+    }
+
+    fn write_element<W: Writer>(&self, element: &Element, level: usize, parent_scope: &Namespace,
+                                inline: bool, target: &mut W) -> IoResult<()> {
+        let mut scope = parent_scope.clone();
+        for (prefix, uri) in element.namespace.uri_mappings() {
+            scope.force_put(prefix, uri);
+        }
+
+        try!(self.write_start_tag(element, level, parent_scope, inline, target));
+
+        let layout = if inline { Layout::Inline } else { layout(element) };
+        match layout {
+            Layout::Empty =>
+                return target.write_str(if self.config.pad_self_closing { " />" } else { "/>" }),
+            Layout::Text | Layout::Inline => {
+                try!(target.write_str(">"));
+                for child in element.children.iter() {
+                    try!(self.write_node(child, level, &scope, true, target));
+                }
+            }
+            Layout::Block => {
+                try!(target.write_str(">"));
+                for child in element.children.iter().filter(|n| !is_whitespace(*n)) {
+                    try!(self.write_newline(level + 1, target));
+                    try!(self.write_node(child, level + 1, &scope, false, target));
+                }
+                try!(self.write_newline(level, target));
+            }
+        }
+        write!(target, "</{}>", element.name.to_repr())
+    }
+
+    /// Writes the start tag without the closing `>`.
+    fn write_start_tag<W: Writer>(&self, element: &Element, level: usize, parent_scope: &Namespace,
+                                  inline: bool, target: &mut W) -> IoResult<()> {
+        let mut declarations: Vec<(Option<&str>, &str)> = element.namespace.uri_mappings()
+            .filter(|&(prefix, uri)| is_new_declaration(parent_scope, prefix, uri))
+            .collect();
+        declarations.sort();
+
+        let mut attributes: Vec<_> = element.attributes.iter().collect();
+        if self.config.sort_attributes {
+            attributes.sort_by(|a, b| a.name.to_repr().cmp(&b.name.to_repr()));
+        }
+
+        let mut items: Vec<String> = Vec::with_capacity(declarations.len() + attributes.len());
+        for &(prefix, uri) in declarations.iter() {
+            items.push(match prefix {
+                Some(prefix) => format!("xmlns:{}=\"{}\"", prefix, escape_str(uri)),
+                None => format!("xmlns=\"{}\"", escape_str(uri))
+            });
+        }
+        for attr in attributes.iter() {
+            items.push(format!("{}=\"{}\"", attr.name.to_repr(), escape_str(attr.value.as_slice())));
+        }
+
+        let name = element.name.to_repr();
+        try!(write!(target, "<{}", name));
+
+        // indentation, "<", name, a space before each item and ">"
+        let width = level * self.indent_width() + 1 + name.chars().count() +
+            items.iter().map(|i| 1 + i.chars().count()).fold(0, |a, b| a + b) + 1;
+        let wrap = !inline && self.config.max_width > 0 &&
+            items.len() > 1 && width > self.config.max_width;
+
+        for item in items.iter() {
+            if wrap {
+                try!(self.write_newline(level + 1, target));
+            } else {
+                try!(target.write_str(" "));
+            }
+            try!(target.write_str(item.as_slice()));
+        }
+        Ok(())
+    }
+
+    fn write_newline<W: Writer>(&self, level: usize, target: &mut W) -> IoResult<()> {
+        try!(target.write_str(self.config.line_separator.as_slice()));
+        let c = if self.config.indent == Indent::Tab { "\t" } else { " " };
+        for _ in iter::range(0, level * self.indent_width()) {
+            try!(target.write_str(c));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn indent_width(&self) -> usize {
+        match self.config.indent {
+            Indent::Spaces(n) => n,
+            Indent::Tab => 1
+        }
+    }
+}
+
+fn is_whitespace(node: &Node) -> bool {
+    match *node {
+        Node::Text(Text(ref data)) => data.chars().all(is_whitespace_char),
+        _ => false
+    }
+}
+
+fn preserves_space(element: &Element) -> bool {
+    element.attributes.iter().any(|a| {
+        a.name.local_name.as_slice() == "space" && a.value.as_slice() == "preserve" &&
+        (a.name.namespace_as_ref() == Some(NS_XML_URI) || a.name.prefix_as_ref() == Some(NS_XML_PREFIX))
+    })
+}
+
+fn layout(element: &Element) -> Layout {
+    if element.children.is_empty() {
+        return Layout::Empty;
+    }
+    if preserves_space(element) {
+        return Layout::Inline;
+    }
+
+    let mut text = false;
+    let mut markup = false;
+    for child in element.children.iter() {
+        match *child {
+            Node::Text(_) if is_whitespace(child) => {}
+            Node::Text(_) | Node::CData(_) => text = true,
+            _ => markup = true
+        }
+    }
+    match (text, markup) {
+        (_, false) => Layout::Text,
+        (false, true) => Layout::Block,
+        (true, true) => Layout::Inline
+    }
+}
+
+/// Checks whether the given mapping of an element's namespace is not in scope of its parent.
+fn is_new_declaration(parent_scope: &Namespace, prefix: Option<&str>, uri: &str) -> bool {
+    match prefix {
+        Some(NS_XML_PREFIX) | Some(NS_XMLNS_PREFIX) => false,
+        _ => match parent_scope.get(&prefix.map(|p| p.to_string())) {
+            Some(parent_uri) => parent_uri != uri,
+            // an empty default namespace only needs to be written to undeclare another one
+            None => !(prefix.is_none() && uri.is_empty())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reader::EventReader;
+    use dom::Document;
+
+    use super::{Formatter, FormatterConfig, Indent};
+
+    fn format(config: FormatterConfig, source: &str) -> String {
+        let mut reader = EventReader::new_from_str_slice(source);
+        let document = Document::from_reader(&mut reader).ok().unwrap();
+        let mut output = Vec::new();
+        Formatter::new(config).format_document(&document, &mut output).ok().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn layouts() {
+        let source = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                      <library xmlns=\"urn:lib\">  <book id=\"1\"><title>A &amp; B</title>\
+                      <note>see <em>this</em>  here </note><!-- c --><empty></empty></book>\
+                      <code xml:space=\"preserve\"><line>  a </line></code></library>";
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                        <library xmlns=\"urn:lib\">\n\
+                        \x20 <book id=\"1\">\n\
+                        \x20   <title>A &amp; B</title>\n\
+                        \x20   <note>see <em>this</em>  here </note>\n\
+                        \x20   <!-- c -->\n\
+                        \x20   <empty />\n\
+                        \x20 </book>\n\
+                        \x20 <code xml:space=\"preserve\"><line>  a </line></code>\n\
+                        </library>\n";
+
+        let formatted = format(FormatterConfig::new(), source);
+        assert_eq!(formatted.as_slice(), expected);
+        // formatting is stable
+        assert_eq!(format(FormatterConfig::new(), formatted.as_slice()), formatted);
+    }
+
+    #[test]
+    fn wrapping_and_sorting() {
+        let config = FormatterConfig::new()
+            .indent(Indent::Tab)
+            .max_width(30)
+            .sort_attributes(true);
+        let source = "<a><item zeta=\"1\" beta=\"2\" alpha=\"3\"/><short b=\"1\" a=\"2\"/></a>";
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                        <a>\n\
+                        \t<item\n\
+                        \t\talpha=\"3\"\n\
+                        \t\tbeta=\"2\"\n\
+                        \t\tzeta=\"1\" />\n\
+                        \t<short a=\"2\" b=\"1\" />\n\
+                        </a>\n";
+
+        let formatted = format(config, source);
+        assert_eq!(formatted.as_slice(), expected);
+    }
+}
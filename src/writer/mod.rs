@@ -1,14 +1,20 @@
 pub use self::emitter::EmitterResult as EventWriterResult;
 pub use self::config::EmitterConfig;
+pub use self::emitter::EmitterError;
+pub use self::builder::{ElementBuilder, ChildWriter};
 
 use std::io::MemWriter;
 
+use name::{Name, IntoName};
+
 use self::emitter::Emitter;
 use self::events::XmlEvent;
 
 mod emitter;
 pub mod config;
 pub mod events;
+pub mod builder;
+pub mod formatter;
 
 pub struct EventWriter<W> {
     sink: W,
@@ -44,9 +50,90 @@ impl<W: Writer> EventWriter<W> {
             XmlEvent::CData(content) => 
                 self.emitter.emit_cdata(&mut self.sink, content),
             XmlEvent::Characters(content) => 
-                self.emitter.emit_characters(&mut self.sink, content)
+                self.emitter.emit_characters(&mut self.sink, content),
+            XmlEvent::Doctype(content) =>
+                self.emitter.emit_doctype(&mut self.sink, content),
+            XmlEvent::EntityReference(name) =>
+                self.emitter.emit_entity_reference(&mut self.sink, name),
+            XmlEvent::RawMarkup(content) =>
+                self.emitter.emit_raw_markup(&mut self.sink, content)
         }
     }
+
+    /// Writes a start tag; attributes and namespace declarations can be added to it with
+    /// `write_attribute` and `write_namespace` until anything else is written.
+    #[inline]
+    pub fn write_start_element(&mut self, name: Name) -> EventWriterResult<()> {
+        self.emitter.emit_start_tag(&mut self.sink, name)
+    }
+
+    /// Same as `write_start_element`, but the element is closed as soon as anything
+    /// else is written, so no `write_end_element` call is needed for it.
+    #[inline]
+    pub fn write_empty_element(&mut self, name: Name) -> EventWriterResult<()> {
+        self.emitter.emit_empty_start_tag(&mut self.sink, name)
+    }
+
+    /// Adds an attribute to the start tag which has just been written.
+    #[inline]
+    pub fn write_attribute(&mut self, name: Name, value: &str) -> EventWriterResult<()> {
+        self.emitter.emit_attribute(&mut self.sink, name, value)
+    }
+
+    /// Adds a namespace declaration to the start tag which has just been written;
+    /// `None` prefix declares the default namespace.
+    #[inline]
+    pub fn write_namespace(&mut self, prefix: Option<&str>, uri: &str) -> EventWriterResult<()> {
+        self.emitter.emit_namespace_declaration(&mut self.sink, prefix, uri)
+    }
+
+    #[inline]
+    pub fn write_characters(&mut self, content: &str) -> EventWriterResult<()> {
+        self.emitter.emit_characters(&mut self.sink, content)
+    }
+
+    #[inline]
+    pub fn write_cdata(&mut self, content: &str) -> EventWriterResult<()> {
+        self.emitter.emit_cdata(&mut self.sink, content)
+    }
+
+    #[inline]
+    pub fn write_comment(&mut self, content: &str) -> EventWriterResult<()> {
+        self.emitter.emit_comment(&mut self.sink, content)
+    }
+
+    #[inline]
+    pub fn write_doctype(&mut self, content: &str) -> EventWriterResult<()> {
+        self.emitter.emit_doctype(&mut self.sink, content)
+    }
+
+    #[inline]
+    pub fn write_entity_reference(&mut self, name: &str) -> EventWriterResult<()> {
+        self.emitter.emit_entity_reference(&mut self.sink, name)
+    }
+
+    #[inline]
+    pub fn write_raw_markup(&mut self, content: &str) -> EventWriterResult<()> {
+        self.emitter.emit_raw_markup(&mut self.sink, content)
+    }
+
+    /// Closes the innermost open element.
+    #[inline]
+    pub fn write_end_element(&mut self) -> EventWriterResult<()> {
+        self.emitter.emit_end_element(&mut self.sink, None)
+    }
+
+    /// Returns a builder which writes an element with the given name, see `builder` module.
+    #[inline]
+    pub fn element<'a, 'b, N: IntoName<'b>>(&'a mut self, name: N) -> ElementBuilder<'a, 'b, W> {
+        ElementBuilder::new(self, name.into_name())
+    }
+
+    /// Closes all open elements.
+    #[inline]
+    pub fn write_end_document(&mut self) -> EventWriterResult<()> {
+        self.emitter.emit_end_document(&mut self.sink)
+    }
 }
 
 impl EventWriter<MemWriter> {
@@ -66,8 +153,9 @@ mod tests {
     use std::io;
     use std::io::{File, BufferedReader, ByRefReader, ByRefWriter};
 
+    use name::Name;
     use reader::EventReader;
-    use writer::EventWriter;
+    use writer::{EventWriter, EmitterConfig};
 
     #[inline]
     fn reader_by_ref<R: Reader>(r: &mut R) -> io::RefReader<R> { r.by_ref() }
@@ -100,4 +188,63 @@ mod tests {
 
         assert_eq!(fs, bs)
     }
+
+    #[test]
+    fn streaming_writer() {
+        let config = EmitterConfig::new().write_document_declaration(false);
+        let mut w = EventWriter::new_with_config(Vec::new(), config);
+
+        w.write_start_element(Name::local("items")).ok().unwrap();
+        w.write_namespace(Some("p"), "urn:p").ok().unwrap();
+        w.write_start_element(Name::local("item")).ok().unwrap();
+        w.write_attribute(Name::local("id"), "1").ok().unwrap();
+        w.write_attribute(Name { local_name: "kind", namespace: None, prefix: Some("p") }, "a&b").ok().unwrap();
+        w.write_characters("text").ok().unwrap();
+        assert!(w.write_attribute(Name::local("late"), "1").is_err());
+        w.write_end_element().ok().unwrap();
+        w.write_empty_element(Name::local("item")).ok().unwrap();
+        w.write_attribute(Name::local("id"), "2").ok().unwrap();
+        w.write_start_element(Name::local("item")).ok().unwrap();
+        w.write_end_document().ok().unwrap();
+
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(),
+                   "<items xmlns:p=\"urn:p\"><item id=\"1\" p:kind=\"a&amp;b\">text</item>\
+                    <item id=\"2\" /><item /></items>");
+    }
+
+    #[test]
+    fn streaming_writer_repairing_namespaces() {
+        let config = EmitterConfig::new()
+            .write_document_declaration(false)
+            .repair_namespaces(true);
+        let mut w = EventWriter::new_with_config(Vec::new(), config);
+
+        w.write_start_element(Name::qualified("root", "urn:r", None)).ok().unwrap();
+        w.write_namespace(None, "urn:r").ok().unwrap();
+        w.write_attribute(Name::qualified("id", "urn:q", None), "1").ok().unwrap();
+        w.write_attribute(Name::qualified("ref", "urn:q", None), "2").ok().unwrap();
+        w.write_end_element().ok().unwrap();
+
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(),
+                   "<root xmlns=\"urn:r\" xmlns:ns0=\"urn:q\" ns0:id=\"1\" ns0:ref=\"2\" />");
+    }
+
+    #[test]
+    fn streaming_writer_empty_root() {
+        let writer = |&:| {
+            let config = EmitterConfig::new().write_document_declaration(false);
+            let mut w = EventWriter::new_with_config(Vec::new(), config);
+            w.write_empty_element(Name::local("root")).ok().unwrap();
+            w
+        };
+
+        assert!(writer().write_characters("tail").is_err());
+        assert!(writer().write_cdata("tail").is_err());
+        assert!(writer().write_entity_reference("nbsp").is_err());
+        assert!(writer().write_start_element(Name::local("b")).is_err());
+
+        let mut w = writer();
+        w.write_characters("\n").ok().unwrap();
+        assert_eq!(String::from_utf8(w.sink).unwrap().as_slice(), "<root />\n");
+    }
 }